@@ -85,6 +85,11 @@ pub fn build_dfa(query: &str) -> DfaExt {
     build_dfa_with_setting(query, PrefixSetting::NoPrefix)
 }
 
+/// Builds an automaton that only accepts the query itself, without typos nor prefix.
+pub fn build_exact_dfa(query: &str) -> DfaExt {
+    DfaExt { query_len: query.len(), automaton: LEVDIST0.build_dfa(query) }
+}
+
 pub trait AutomatonExt: Automaton {
     fn eval<B: AsRef<[u8]>>(&self, s: B) -> Distance;
     fn query_len(&self) -> usize;
@@ -4,7 +4,7 @@ use crate::criterion::Criterion;
 use crate::RawDocument;
 
 #[inline]
-fn sum_matches_attribute_index(query_index: &[u32], word_index: &[u16]) -> usize {
+fn sum_matches_attribute_index(query_index: &[u32], word_index: &[u32]) -> usize {
     let mut sum_word_index = 0;
     let mut index = 0;
 
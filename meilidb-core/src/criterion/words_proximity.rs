@@ -10,20 +10,21 @@ fn clone_tuple<T: Clone, U: Clone>((a, b): (&T, &U)) -> (T, U) {
     (a.clone(), b.clone())
 }
 
-fn index_proximity(lhs: u16, rhs: u16) -> u16 {
+fn index_proximity(lhs: u32, rhs: u32) -> u16 {
+    let max_distance = u32::from(MAX_DISTANCE);
     if lhs < rhs {
-        cmp::min(rhs - lhs, MAX_DISTANCE)
+        cmp::min(rhs - lhs, max_distance) as u16
     } else {
-        cmp::min(lhs - rhs, MAX_DISTANCE) + 1
+        cmp::min(lhs - rhs, max_distance) as u16 + 1
     }
 }
 
-fn attribute_proximity((lattr, lwi): (u16, u16), (rattr, rwi): (u16, u16)) -> u16 {
+fn attribute_proximity((lattr, lwi): (u16, u32), (rattr, rwi): (u16, u32)) -> u16 {
     if lattr != rattr { return MAX_DISTANCE }
     index_proximity(lwi, rwi)
 }
 
-fn min_proximity((lattr, lwi): (&[u16], &[u16]), (rattr, rwi): (&[u16], &[u16])) -> u16 {
+fn min_proximity((lattr, lwi): (&[u16], &[u32]), (rattr, rwi): (&[u16], &[u32])) -> u16 {
     let mut min_prox = u16::max_value();
 
     for a in lattr.iter().zip(lwi) {
@@ -41,7 +42,7 @@ fn matches_proximity(
     query_index: &[u32],
     distance: &[u8],
     attribute: &[u16],
-    word_index: &[u16],
+    word_index: &[u32],
 ) -> u16
 {
     let mut query_index_groups = query_index.linear_group();
@@ -36,6 +36,11 @@ impl DocIndexes {
         })
     }
 
+    /// Returns the total number of `DocIndex` of all the ranges.
+    pub fn doc_indexes_len(&self) -> usize {
+        self.indexes().len()
+    }
+
     fn ranges(&self) -> &[Range] {
         let slice = &self.ranges;
         let ptr = slice.as_ptr() as *const Range;
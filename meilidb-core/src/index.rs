@@ -1,4 +1,5 @@
 use std::error::Error;
+use std::io::Cursor;
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use fst::{map, Map, IntoStreamer, Streamer};
@@ -19,6 +20,37 @@ pub struct Index {
 
 impl Index {
     pub fn remove_documents(&self, documents: &Set<DocumentId>) -> Index {
+        self.remove_by_key(documents, |x| x.document_id)
+    }
+
+    pub fn remove_documents_attributes(&self, attributes: &Set<(DocumentId, u16)>) -> Index {
+        self.remove_by_key(attributes, |x| (x.document_id, x.attribute))
+    }
+
+    pub fn remove_attributes(&self, attributes: &Set<u16>) -> Index {
+        let mut buffer = Vec::new();
+        let mut builder = IndexBuilder::new();
+        let mut stream = self.into_stream();
+
+        while let Some((key, indexes)) = stream.next() {
+            buffer.clear();
+
+            let iter = indexes.iter().filter(|x| attributes.binary_search(&x.attribute).is_err());
+            buffer.extend(iter.cloned());
+
+            if !buffer.is_empty() {
+                let indexes = Set::new_unchecked(&buffer);
+                builder.insert(key, indexes).unwrap();
+            }
+        }
+
+        builder.build()
+    }
+
+    /// Returns the same index where the document ids are replaced by the given ones.
+    pub fn map_documents<F>(&self, mut f: F) -> Index
+    where F: FnMut(DocumentId) -> DocumentId,
+    {
         let mut buffer = Vec::new();
         let mut builder = IndexBuilder::new();
         let mut stream = self.into_stream();
@@ -26,7 +58,39 @@ impl Index {
         while let Some((key, indexes)) = stream.next() {
             buffer.clear();
 
-            let op = DifferenceByKey::new(indexes, documents, |x| x.document_id, |x| *x);
+            let iter = indexes.iter().map(|x| DocIndex { document_id: f(x.document_id), ..*x });
+            buffer.extend(iter);
+            buffer.sort_unstable();
+
+            let indexes = Set::new_unchecked(&buffer);
+            builder.insert(key, indexes).unwrap();
+        }
+
+        builder.build()
+    }
+
+    /// Calls the function with every word and its postings, in lexicographic order.
+    pub fn for_each_word<F>(&self, mut f: F)
+    where F: FnMut(&[u8], &Set<DocIndex>),
+    {
+        let mut stream = self.into_stream();
+        while let Some((word, indexes)) = stream.next() {
+            f(word, indexes);
+        }
+    }
+
+    fn remove_by_key<K, F>(&self, keys: &Set<K>, f: F) -> Index
+    where K: Ord + Copy,
+          F: Fn(&DocIndex) -> K,
+    {
+        let mut buffer = Vec::new();
+        let mut builder = IndexBuilder::new();
+        let mut stream = self.into_stream();
+
+        while let Some((key, indexes)) = stream.next() {
+            buffer.clear();
+
+            let op = DifferenceByKey::new(indexes, keys, &f, |x| *x);
             op.extend_vec(&mut buffer);
 
             if !buffer.is_empty() {
@@ -74,6 +138,48 @@ impl Index {
 
         builder.build()
     }
+
+    /// Reads an index written by the versions that stored the whole word index under
+    /// a single key, their document indexes had `u16` word and char indexes.
+    pub fn from_legacy_bytes(bytes: &[u8]) -> Result<Index, Box<Error>> {
+        let mut cursor = Cursor::new(bytes);
+
+        let len = cursor.read_u64::<LittleEndian>()? as usize;
+        let start = cursor.position() as usize;
+        let fst = bytes.get(start..start + len).ok_or("truncated words map")?;
+        let map = Map::from_bytes(fst.to_vec())?;
+        cursor.set_position((start + len) as u64);
+
+        let len = cursor.read_u64::<LittleEndian>()? as usize;
+        let mut ranges = Vec::with_capacity(len / 16);
+        for _ in 0..len / 16 {
+            let start = cursor.read_u64::<LittleEndian>()? as usize;
+            let end = cursor.read_u64::<LittleEndian>()? as usize;
+            ranges.push(start..end);
+        }
+
+        let len = cursor.read_u64::<LittleEndian>()? as usize;
+        let mut indexes = Vec::with_capacity(len / 16);
+        for _ in 0..len / 16 {
+            indexes.push(DocIndex {
+                document_id: DocumentId(cursor.read_u64::<LittleEndian>()?),
+                attribute: cursor.read_u16::<LittleEndian>()?,
+                word_index: u32::from(cursor.read_u16::<LittleEndian>()?),
+                char_index: u32::from(cursor.read_u16::<LittleEndian>()?),
+                char_length: cursor.read_u16::<LittleEndian>()?,
+            });
+        }
+
+        let mut builder = IndexBuilder::new();
+        let mut stream = map.into_stream();
+        while let Some((key, value)) = stream.next() {
+            let range = ranges.get(value as usize).ok_or("missing postings range")?;
+            let indexes = indexes.get(range.clone()).ok_or("missing postings")?;
+            builder.insert(key, Set::new_unchecked(indexes))?;
+        }
+
+        Ok(builder.build())
+    }
 }
 
 impl FromSharedDataCursor for Index {
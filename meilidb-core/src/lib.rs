@@ -1,6 +1,8 @@
 pub mod criterion;
 pub mod data;
 mod index;
+mod postings;
+mod segmented_index;
 mod automaton;
 mod query_builder;
 mod distinct_map;
@@ -15,7 +17,10 @@ use slice_group_by::GroupBy;
 use rayon::slice::ParallelSliceMut;
 
 pub use self::index::{Index, IndexBuilder};
-pub use self::query_builder::{QueryBuilder, DistinctQueryBuilder};
+pub use self::segmented_index::{SegmentedIndex, Segment, Store, merge_segments};
+pub use self::segmented_index::mmap_words;
+pub use self::postings::{Postings, PostingsIter, PostingsEncoding, write_postings};
+pub use self::query_builder::{QueryBuilder, DistinctQueryBuilder, Filter, SearchResult};
 
 /// Represent an internally generated document unique identifier.
 ///
@@ -30,6 +35,8 @@ pub struct DocumentId(pub u64);
 ///
 /// This is stored in the map, generated at index time,
 /// extracted and interpreted at search time.
+///
+/// The word and char indexes are 32 bits wide to find the words of long attributes.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[repr(C)]
 pub struct DocIndex {
@@ -39,14 +46,14 @@ pub struct DocIndex {
     /// The attribute in the document where the word was found
     /// along with the index in it.
     pub attribute: u16,
-    pub word_index: u16,
+    pub word_index: u32,
 
     /// The position in bytes where the word was found
     /// along with the length of it.
     ///
     /// It informs on the original word area in the text indexed
     /// without needing to run the tokenizer again.
-    pub char_index: u16,
+    pub char_index: u32,
     pub char_length: u16,
 }
 
@@ -73,7 +80,7 @@ pub struct Match {
     /// The attribute in the document where the word was found
     /// along with the index in it.
     pub attribute: u16,
-    pub word_index: u16,
+    pub word_index: u32,
 
     /// Whether the word that match is an exact match or a prefix.
     pub is_exact: bool,
@@ -83,7 +90,7 @@ pub struct Match {
     ///
     /// It informs on the original word area in the text indexed
     /// without needing to run the tokenizer again.
-    pub char_index: u16,
+    pub char_index: u32,
     pub char_length: u16,
 }
 
@@ -105,9 +112,9 @@ impl Match {
             query_index: u32::max_value(),
             distance: u8::max_value(),
             attribute: u16::max_value(),
-            word_index: u16::max_value(),
+            word_index: u32::max_value(),
             is_exact: true,
-            char_index: u16::max_value(),
+            char_index: u32::max_value(),
             char_length: u16::max_value(),
         }
     }
@@ -181,7 +188,7 @@ impl RawDocument {
         unsafe { &self.matches.matches.attribute.get_unchecked(r.start..r.end) }
     }
 
-    pub fn word_index(&self) -> &[u16] {
+    pub fn word_index(&self) -> &[u32] {
         let r = self.matches.range;
         // it is safe because construction/modifications
         // can only be done in this module
@@ -195,7 +202,7 @@ impl RawDocument {
         unsafe { &self.matches.matches.is_exact.get_unchecked(r.start..r.end) }
     }
 
-    pub fn char_index(&self) -> &[u16] {
+    pub fn char_index(&self) -> &[u32] {
         let r = self.matches.range;
         // it is safe because construction/modifications
         // can only be done in this module
@@ -252,9 +259,9 @@ struct Matches {
     query_index: Vec<u32>,
     distance: Vec<u8>,
     attribute: Vec<u16>,
-    word_index: Vec<u16>,
+    word_index: Vec<u32>,
     is_exact: Vec<bool>,
-    char_index: Vec<u16>,
+    char_index: Vec<u32>,
     char_length: Vec<u16>,
 }
 
@@ -292,6 +299,6 @@ mod tests {
 
     #[test]
     fn docindex_mem_size() {
-        assert_eq!(mem::size_of::<DocIndex>(), 16);
+        assert_eq!(mem::size_of::<DocIndex>(), 24);
     }
 }
@@ -0,0 +1,271 @@
+use std::io;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use sdset::{Set, SetBuf};
+use serde::{Serialize, Deserialize};
+
+use crate::{DocumentId, DocIndex};
+
+/// The way the postings of a word are encoded to be stored, whatever the encoding
+/// they are checksummed when read and decoded field by field while iterated.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PostingsEncoding {
+    /// Every field is written with a fixed size, a posting takes 20 bytes.
+    Raw,
+    /// The document ids are delta encoded and all the fields are written as varints,
+    /// a posting takes around 6 bytes but the fields can not be read at a fixed offset.
+    Compressed,
+}
+
+impl Default for PostingsEncoding {
+    fn default() -> PostingsEncoding {
+        PostingsEncoding::Raw
+    }
+}
+
+impl PostingsEncoding {
+    fn tag(self) -> u8 {
+        match self {
+            PostingsEncoding::Raw => 0,
+            PostingsEncoding::Compressed => 1,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<PostingsEncoding> {
+        match tag {
+            0 => Some(PostingsEncoding::Raw),
+            1 => Some(PostingsEncoding::Compressed),
+            _ => None,
+        }
+    }
+}
+
+fn corrupted(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+    let mut shift = 0;
+
+    loop {
+        let (byte, rest) = bytes.split_first()?;
+        *bytes = rest;
+
+        if shift >= 64 { return None }
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 { return Some(value) }
+        shift += 7;
+    }
+}
+
+/// Encodes the postings of a word to be stored apart from its segment, the encoding
+/// is written first and the postings are followed by the checksum of their bytes.
+pub fn write_postings(indexes: &Set<DocIndex>, encoding: PostingsEncoding, bytes: &mut Vec<u8>) {
+    let start = bytes.len();
+    bytes.push(encoding.tag());
+
+    match encoding {
+        PostingsEncoding::Raw => {
+            for doc_index in indexes.as_slice() {
+                let _ = bytes.write_u64::<LittleEndian>(doc_index.document_id.0);
+                let _ = bytes.write_u16::<LittleEndian>(doc_index.attribute);
+                let _ = bytes.write_u32::<LittleEndian>(doc_index.word_index);
+                let _ = bytes.write_u32::<LittleEndian>(doc_index.char_index);
+                let _ = bytes.write_u16::<LittleEndian>(doc_index.char_length);
+            }
+        },
+        PostingsEncoding::Compressed => {
+            let mut last_document_id = 0;
+            for doc_index in indexes.as_slice() {
+                let DocumentId(document_id) = doc_index.document_id;
+                write_varint(bytes, document_id - last_document_id);
+                write_varint(bytes, u64::from(doc_index.attribute));
+                write_varint(bytes, u64::from(doc_index.word_index));
+                write_varint(bytes, u64::from(doc_index.char_index));
+                write_varint(bytes, u64::from(doc_index.char_length));
+                last_document_id = document_id;
+            }
+        },
+    }
+
+    let checksum = crc32fast::hash(&bytes[start..]);
+    let _ = bytes.write_u32::<LittleEndian>(checksum);
+}
+
+/// The postings of a word written by `write_postings`, they are decoded while iterated.
+#[derive(Debug, Clone)]
+pub struct Postings {
+    encoding: PostingsEncoding,
+    // starts with the encoding tag
+    bytes: Vec<u8>,
+}
+
+impl Postings {
+    /// Verifies the checksum and the encoding of the postings,
+    /// returns an `InvalidData` error if they are corrupted.
+    pub fn from_bytes(mut bytes: Vec<u8>) -> io::Result<Postings> {
+        if bytes.len() < 5 {
+            return Err(corrupted("truncated postings"))
+        }
+
+        let mut checksum = &bytes[bytes.len() - 4..];
+        let checksum = checksum.read_u32::<LittleEndian>()?;
+        bytes.truncate(bytes.len() - 4);
+
+        if crc32fast::hash(&bytes) != checksum {
+            return Err(corrupted("postings checksum mismatch"))
+        }
+
+        let encoding = match PostingsEncoding::from_tag(bytes[0]) {
+            Some(encoding) => encoding,
+            None => return Err(corrupted("unknown postings encoding")),
+        };
+
+        if encoding == PostingsEncoding::Raw && (bytes.len() - 1) % 20 != 0 {
+            return Err(corrupted("truncated postings"))
+        }
+
+        Ok(Postings { encoding, bytes })
+    }
+
+    pub fn encoding(&self) -> PostingsEncoding {
+        self.encoding
+    }
+
+    pub fn iter(&self) -> PostingsIter {
+        PostingsIter { encoding: self.encoding, bytes: &self.bytes[1..], last_document_id: 0 }
+    }
+
+    pub fn to_set_buf(&self) -> SetBuf<DocIndex> {
+        SetBuf::new_unchecked(self.iter().collect())
+    }
+}
+
+/// Decodes the postings one by one, in the order they were written.
+pub struct PostingsIter<'a> {
+    encoding: PostingsEncoding,
+    bytes: &'a [u8],
+    last_document_id: u64,
+}
+
+impl<'a> Iterator for PostingsIter<'a> {
+    type Item = DocIndex;
+
+    fn next(&mut self) -> Option<DocIndex> {
+        if self.bytes.is_empty() { return None }
+
+        match self.encoding {
+            PostingsEncoding::Raw => {
+                let bytes = &mut self.bytes;
+                Some(DocIndex {
+                    document_id: bytes.read_u64::<LittleEndian>().map(DocumentId).ok()?,
+                    attribute: bytes.read_u16::<LittleEndian>().ok()?,
+                    word_index: bytes.read_u32::<LittleEndian>().ok()?,
+                    char_index: bytes.read_u32::<LittleEndian>().ok()?,
+                    char_length: bytes.read_u16::<LittleEndian>().ok()?,
+                })
+            },
+            PostingsEncoding::Compressed => {
+                let bytes = &mut self.bytes;
+                let document_id = self.last_document_id + read_varint(bytes)?;
+                self.last_document_id = document_id;
+
+                Some(DocIndex {
+                    document_id: DocumentId(document_id),
+                    attribute: read_varint(bytes)? as u16,
+                    word_index: read_varint(bytes)? as u32,
+                    char_index: read_varint(bytes)? as u32,
+                    char_length: read_varint(bytes)? as u16,
+                })
+            },
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.encoding {
+            PostingsEncoding::Raw => {
+                let len = self.bytes.len() / 20;
+                (len, Some(len))
+            },
+            PostingsEncoding::Compressed => {
+                // a compressed posting takes at least 5 bytes
+                (0, Some(self.bytes.len() / 5))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn postings() -> SetBuf<DocIndex> {
+        let mut indexes = Vec::new();
+        for document_id in &[0, 1, 2, 300, 70_000, u64::max_value()] {
+            for (attribute, word_index) in &[(0, 0), (0, 1), (2, 70_000), (u16::max_value(), 5)] {
+                indexes.push(DocIndex {
+                    document_id: DocumentId(*document_id),
+                    attribute: *attribute,
+                    word_index: *word_index,
+                    char_index: word_index * 7,
+                    char_length: 8,
+                });
+            }
+        }
+        SetBuf::new_unchecked(indexes)
+    }
+
+    #[test]
+    fn raw_serialize_deserialize() {
+        let indexes = postings();
+
+        let mut bytes = Vec::new();
+        write_postings(&indexes, PostingsEncoding::Raw, &mut bytes);
+        assert_eq!(bytes.len(), 1 + indexes.len() * 20 + 4);
+
+        let postings = Postings::from_bytes(bytes).unwrap();
+        assert_eq!(postings.to_set_buf(), indexes);
+    }
+
+    #[test]
+    fn compressed_serialize_deserialize() {
+        let indexes = postings();
+
+        let mut raw_bytes = Vec::new();
+        write_postings(&indexes, PostingsEncoding::Raw, &mut raw_bytes);
+
+        let mut bytes = Vec::new();
+        write_postings(&indexes, PostingsEncoding::Compressed, &mut bytes);
+        assert!(bytes.len() < raw_bytes.len());
+
+        let postings = Postings::from_bytes(bytes).unwrap();
+        assert_eq!(postings.encoding(), PostingsEncoding::Compressed);
+        assert_eq!(postings.to_set_buf(), indexes);
+    }
+
+    #[test]
+    fn corrupted_postings() {
+        let indexes = postings();
+
+        for encoding in &[PostingsEncoding::Raw, PostingsEncoding::Compressed] {
+            let mut bytes = Vec::new();
+            write_postings(&indexes, *encoding, &mut bytes);
+
+            let mut flipped = bytes.clone();
+            flipped[3] ^= 1;
+            assert!(Postings::from_bytes(flipped).is_err());
+
+            bytes.truncate(20);
+            assert!(Postings::from_bytes(bytes).is_err());
+        }
+    }
+}
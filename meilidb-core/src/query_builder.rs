@@ -1,71 +1,370 @@
 use std::hash::Hash;
-use std::ops::{Range, Deref};
+use std::ops::Range;
 use std::rc::Rc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use std::{cmp, mem};
 
 use rayon::slice::ParallelSliceMut;
 use slice_group_by::GroupByMut;
-use meilidb_tokenizer::{is_cjk, split_query_string};
+use meilidb_tokenizer::{is_cjk, Tokenizer};
 use hashbrown::{HashMap, HashSet};
 use fst::Streamer;
 use log::info;
+use sdset::SetBuf;
 
 use crate::automaton::{self, DfaExt, AutomatonExt};
 use crate::distinct_map::{DistinctMap, BufferedDistinctMap};
 use crate::criterion::Criteria;
 use crate::{raw_documents_from_matches, RawDocument, Document};
-use crate::{Index, Match, DocumentId};
+use crate::{Store, Match, DocumentId};
 
-fn generate_automatons(query: &str) -> Vec<DfaExt> {
-    let has_end_whitespace = query.chars().last().map_or(false, char::is_whitespace);
-    let mut groups = split_query_string(query).map(str::to_lowercase).peekable();
-    let mut automatons = Vec::new();
+/// A word of the query, only the last one can be a prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct QueryWord {
+    word: String,
+    prefix: bool,
+}
+
+impl QueryWord {
+    fn new(word: &str) -> QueryWord {
+        QueryWord { word: word.to_lowercase(), prefix: false }
+    }
+}
+
+/// A term of the query, documents matching more terms are ranked first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum QueryTree {
+    Word(QueryWord),
+    /// Words written between double quotes along with their word index offset
+    /// from the first word, documents must contain them in the same layout.
+    Phrase(Vec<(QueryWord, u32)>),
+    /// Words separated by `OR`, they are considered as the same query word.
+    /// The words of an operand like `c-lang` are all alternatives of the term.
+    Or(Vec<QueryWord>),
+    /// Words preceded by a minus along with their word index offset,
+    /// documents containing them in the same layout are removed.
+    Not(Vec<(QueryWord, u32)>),
+}
+
+impl QueryTree {
+    fn last_word_mut(&mut self) -> Option<&mut QueryWord> {
+        match self {
+            QueryTree::Word(word) => Some(word),
+            QueryTree::Phrase(words) => words.last_mut().map(|(word, _)| word),
+            QueryTree::Or(words) => words.last_mut(),
+            QueryTree::Not(_) => None,
+        }
+    }
+}
+
+/// Words of the query that must be found in the same attribute of a document
+/// and at the same distance from each other than in the query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Phrase {
+    /// The query index of each word along with its word index offset from the first word.
+    words: Vec<(u32, u32)>,
+}
 
-    while let Some(word) = groups.next() {
-        let has_following_word = groups.peek().is_some();
-        let lev = if has_following_word || has_end_whitespace || word.chars().all(is_cjk) {
-            automaton::build_dfa(&word)
+/// A group of words of the query as written by the user.
+enum QueryItem {
+    Words { words: Vec<(QueryWord, u32)>, quoted: bool, negated: bool },
+    Or,
+}
+
+fn tokenize_words(text: &str) -> Vec<(QueryWord, u32)> {
+    let tokens: Vec<_> = Tokenizer::new(text).collect();
+    let first = tokens.first().map_or(0, |token| token.word_index);
+    tokens.iter().map(|token| {
+        let offset = (token.word_index - first) as u32;
+        (QueryWord::new(token.word), offset)
+    }).collect()
+}
+
+/// Splits the query into its words written between double quotes, the words preceded
+/// by a minus and the `OR` operators, an unterminated double quote ends with the query.
+fn split_query_items(query: &str) -> Vec<QueryItem> {
+    let mut items = Vec::new();
+    let mut negate_quoted = false;
+
+    for (i, part) in query.split('"').enumerate() {
+        if i % 2 == 1 {
+            let words = tokenize_words(part);
+            items.push(QueryItem::Words { words, quoted: true, negated: negate_quoted });
+            continue;
+        }
+
+        for chunk in part.split_whitespace() {
+            if chunk == "OR" {
+                items.push(QueryItem::Or);
+            } else {
+                let words = tokenize_words(chunk);
+                let negated = chunk.starts_with('-');
+                items.push(QueryItem::Words { words, quoted: false, negated });
+            }
+        }
+
+        // a minus directly followed by a double quote negates the phrase
+        negate_quoted = part.ends_with('-');
+    }
+
+    items
+}
+
+/// Returns the words of an item that can be joined by an `OR` operator.
+fn or_operand(item: Option<&QueryItem>) -> Option<&[(QueryWord, u32)]> {
+    match item {
+        Some(QueryItem::Words { words, quoted: false, negated: false }) if !words.is_empty() => {
+            Some(words)
+        },
+        _ => None,
+    }
+}
+
+/// Parses the query into a list of terms, the `OR` operators only apply to the words around
+/// them and are considered as ordinary words when there is no word to join.
+///
+/// Every word of an operand made of many words, like `c-lang`, is an alternative of the term.
+fn parse_query(query: &str) -> Vec<QueryTree> {
+    let items = split_query_items(query);
+    let mut trees = Vec::new();
+
+    let mut i = 0;
+    while i < items.len() {
+        let item = &items[i];
+        i += 1;
+
+        let (words, quoted, negated) = match item {
+            QueryItem::Words { words, quoted, negated } => (words, *quoted, *negated),
+            QueryItem::Or => {
+                trees.push(QueryTree::Word(QueryWord::new("OR")));
+                continue;
+            },
+        };
+
+        if let Some(operand) = or_operand(Some(item)) {
+            let mut alternatives: Vec<_> = operand.iter().map(|(word, _)| word.clone()).collect();
+            let mut joined = false;
+
+            while let (Some(QueryItem::Or), Some(next)) = (items.get(i), or_operand(items.get(i + 1))) {
+                alternatives.extend(next.iter().map(|(word, _)| word.clone()));
+                joined = true;
+                i += 2;
+            }
+
+            if joined {
+                trees.push(QueryTree::Or(alternatives));
+                continue;
+            }
+        }
+
+        if words.is_empty() { continue }
+
+        if negated {
+            trees.push(QueryTree::Not(words.clone()));
+        } else if quoted && words.len() > 1 {
+            trees.push(QueryTree::Phrase(words.clone()));
         } else {
-            automaton::build_prefix_dfa(&word)
+            trees.extend(words.iter().map(|(word, _)| QueryTree::Word(word.clone())));
+        }
+    }
+
+    // a whitespace or a closing double quote ends the last word
+    let ends_last_word = query.chars().last().map_or(false, |c| c.is_whitespace() || c == '"');
+    if !ends_last_word {
+        if let Some(word) = trees.last_mut().and_then(QueryTree::last_word_mut) {
+            word.prefix = !word.word.chars().all(is_cjk);
+        }
+    }
+
+    trees
+}
+
+/// The automatons of the words of a query, the words to exclude are given query indexes
+/// of their own and their matches must not be mixed with the other ones.
+struct QueryAutomatons {
+    automatons: Vec<DfaExt>,
+    query_indexes: Vec<u32>,
+    excluded: Vec<bool>,
+    phrases: Vec<Phrase>,
+    excluded_phrases: Vec<Phrase>,
+}
+
+impl QueryAutomatons {
+    fn from_query_trees(trees: &[QueryTree]) -> QueryAutomatons {
+        let mut query_automatons = QueryAutomatons {
+            automatons: Vec::new(),
+            query_indexes: Vec::new(),
+            excluded: Vec::new(),
+            phrases: Vec::new(),
+            excluded_phrases: Vec::new(),
         };
-        automatons.push(lev);
+
+        let mut query_index = 0;
+        let mut excluded_index = 0;
+
+        for tree in trees {
+            match tree {
+                QueryTree::Word(word) => {
+                    query_automatons.push(word, query_index, false);
+                    query_index += 1;
+                },
+                QueryTree::Phrase(words) => {
+                    let phrase = query_automatons.push_phrase(words, query_index, false);
+                    query_automatons.phrases.push(phrase);
+                    query_index += words.len() as u32;
+                },
+                QueryTree::Or(words) => {
+                    for word in words {
+                        query_automatons.push(word, query_index, false);
+                    }
+                    query_index += 1;
+                },
+                QueryTree::Not(words) => {
+                    let phrase = query_automatons.push_phrase(words, excluded_index, true);
+                    query_automatons.excluded_phrases.push(phrase);
+                    excluded_index += words.len() as u32;
+                },
+            }
+        }
+
+        query_automatons
+    }
+
+    fn push(&mut self, word: &QueryWord, query_index: u32, excluded: bool) {
+        // a document is only removed if it contains the excluded word itself
+        let automaton = if excluded {
+            automaton::build_exact_dfa(&word.word)
+        } else if word.prefix {
+            automaton::build_prefix_dfa(&word.word)
+        } else {
+            automaton::build_dfa(&word.word)
+        };
+
+        self.automatons.push(automaton);
+        self.query_indexes.push(query_index);
+        self.excluded.push(excluded);
+    }
+
+    fn push_phrase(&mut self, words: &[(QueryWord, u32)], start: u32, excluded: bool) -> Phrase {
+        let mut phrase = Vec::with_capacity(words.len());
+        for (i, (word, offset)) in words.iter().enumerate() {
+            let query_index = start + i as u32;
+            self.push(word, query_index, excluded);
+            phrase.push((query_index, *offset));
+        }
+        Phrase { words: phrase }
+    }
+}
+
+fn generate_automatons(query: &str) -> QueryAutomatons {
+    let trees = parse_query(query);
+    QueryAutomatons::from_query_trees(&trees)
+}
+
+/// Returns whether the matches of a document contain every word of the phrase
+/// in the same attribute and at the expected word index offsets.
+fn contains_phrase(
+    phrase: &Phrase,
+    query_index: &[u32],
+    attribute: &[u16],
+    word_index: &[u32],
+) -> bool
+{
+    let (first, others) = match phrase.words.split_first() {
+        Some(((first, _), others)) => (*first, others),
+        None => return true,
+    };
+
+    let mut positions = HashSet::with_capacity(query_index.len());
+    for i in 0..query_index.len() {
+        positions.insert((query_index[i], attribute[i], word_index[i]));
+    }
+
+    (0..query_index.len()).filter(|i| query_index[*i] == first).any(|i| {
+        others.iter().all(|(qi, offset)| {
+            match word_index[i].checked_add(*offset) {
+                Some(wi) => positions.contains(&(*qi, attribute[i], wi)),
+                None => false,
+            }
+        })
+    })
+}
+
+/// Decides which documents can be returned by a query,
+/// any `Fn(DocumentId) -> bool` function is a filter.
+pub trait Filter {
+    fn accept(&self, id: DocumentId) -> bool;
+}
+
+impl<F> Filter for F
+where F: Fn(DocumentId) -> bool,
+{
+    fn accept(&self, id: DocumentId) -> bool {
+        (self)(id)
     }
+}
 
-    automatons
+/// The documents in the requested range along with the number of documents
+/// matching the query, the filter and the distinct rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    pub hits: Vec<Document>,
+    /// The number of matching documents, it is an estimation if not exhaustive.
+    pub total_hits: usize,
+    /// Whether every matching document was given to the filter and distinct rule.
+    pub exhaustive: bool,
+    pub processing_time: Duration,
 }
 
-pub struct QueryBuilder<'c, I, FI = fn(DocumentId) -> bool> {
-    index: I,
+pub struct QueryBuilder<'c, S, FI = fn(DocumentId) -> bool> {
+    store: S,
     criteria: Criteria<'c>,
     searchable_attrs: Option<HashSet<u16>>,
     filter: Option<FI>,
+    exhaustive_count: bool,
 }
 
-impl<'c, I> QueryBuilder<'c, I, fn(DocumentId) -> bool> {
-    pub fn new(index: I) -> Self {
-        QueryBuilder::with_criteria(index, Criteria::default())
+impl<'c, S> QueryBuilder<'c, S, fn(DocumentId) -> bool> {
+    pub fn new(store: S) -> Self {
+        QueryBuilder::with_criteria(store, Criteria::default())
     }
 
-    pub fn with_criteria(index: I, criteria: Criteria<'c>) -> Self {
-        QueryBuilder { index, criteria, searchable_attrs: None, filter: None }
+    pub fn with_criteria(store: S, criteria: Criteria<'c>) -> Self {
+        QueryBuilder {
+            store,
+            criteria,
+            searchable_attrs: None,
+            filter: None,
+            exhaustive_count: true,
+        }
     }
 }
 
-impl<'c, I, FI> QueryBuilder<'c, I, FI>
+impl<'c, S, FI> QueryBuilder<'c, S, FI>
 {
-    pub fn with_filter<F>(self, function: F) -> QueryBuilder<'c, I, F>
+    pub fn with_filter<F>(self, function: F) -> QueryBuilder<'c, S, F>
     where F: Fn(DocumentId) -> bool,
+    {
+        self.with_custom_filter(function)
+    }
+
+    pub fn with_custom_filter<F>(self, filter: F) -> QueryBuilder<'c, S, F>
+    where F: Filter,
     {
         QueryBuilder {
-            index: self.index,
+            store: self.store,
             criteria: self.criteria,
             searchable_attrs: self.searchable_attrs,
-            filter: Some(function)
+            filter: Some(filter),
+            exhaustive_count: self.exhaustive_count,
         }
     }
 
-    pub fn with_distinct<F, K>(self, function: F, size: usize) -> DistinctQueryBuilder<'c, I, FI, F>
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn with_distinct<F, K>(self, function: F, size: usize) -> DistinctQueryBuilder<'c, S, FI, F>
     where F: Fn(DocumentId) -> Option<K>,
           K: Hash + Eq,
     {
@@ -80,66 +379,117 @@ impl<'c, I, FI> QueryBuilder<'c, I, FI>
         let attributes = self.searchable_attrs.get_or_insert_with(HashSet::new);
         attributes.insert(attribute);
     }
+
+    /// Whether the filter and the distinct rule are evaluated on every matching document
+    /// to count them, otherwise the total is estimated from the documents sorted.
+    ///
+    /// The count is exact by default, it can be estimated as evaluating every document
+    /// can be much slower than the query itself when the filter reads the stored documents.
+    pub fn set_exhaustive_count(&mut self, exhaustive: bool) {
+        self.exhaustive_count = exhaustive;
+    }
 }
 
-impl<'c, I, FI> QueryBuilder<'c, I, FI>
-where I: Deref<Target=Index>,
+impl<'c, S, FI> QueryBuilder<'c, S, FI>
+where S: Store,
 {
-    fn query_all(&self, query: &str) -> Vec<RawDocument> {
-        let automatons = generate_automatons(query);
-
-        let mut stream = {
-            let mut op_builder = fst::map::OpBuilder::new();
-            for automaton in &automatons {
-                let stream = self.index.map.search(automaton);
-                op_builder.push(stream);
-            }
-            op_builder.r#union()
-        };
+    fn query_all(&self, query: &str) -> Result<Vec<RawDocument>, S::Error> {
+        let query = generate_automatons(query);
+        let automatons = &query.automatons;
+        let word_index = self.store.word_index();
 
         let mut matches = Vec::new();
+        let mut excluded_matches = Vec::new();
+
+        for (si, segment) in word_index.segments().iter().enumerate() {
+            let mut stream = {
+                let mut op_builder = fst::map::OpBuilder::new();
+                for automaton in automatons {
+                    let stream = segment.words.search(automaton);
+                    op_builder.push(stream);
+                }
+                op_builder.r#union()
+            };
 
-        while let Some((input, indexed_values)) = stream.next() {
-            for iv in indexed_values {
-                let automaton = &automatons[iv.index];
-                let distance = automaton.eval(input).to_u8();
-                let is_exact = distance == 0 && input.len() == automaton.query_len();
-
-                let doc_indexes = &self.index.indexes;
-                let doc_indexes = &doc_indexes[iv.value as usize];
-
-                for di in doc_indexes {
-                    if self.searchable_attrs.as_ref().map_or(true, |r| r.contains(&di.attribute)) {
-                        let match_ = Match {
-                            query_index: iv.index as u32,
-                            distance: distance,
-                            attribute: di.attribute,
-                            word_index: di.word_index,
-                            is_exact: is_exact,
-                            char_index: di.char_index,
-                            char_length: di.char_length,
-                        };
-                        matches.push((di.document_id, match_));
+            while let Some((input, indexed_values)) = stream.next() {
+                // the postings are only loaded for the words that match
+                let doc_indexes = match self.store.word_indexes(segment.id, input)? {
+                    Some(doc_indexes) => doc_indexes,
+                    None => continue,
+                };
+
+                for iv in indexed_values {
+                    let automaton = &automatons[iv.index];
+                    let distance = automaton.eval(input).to_u8();
+                    let is_exact = distance == 0 && input.len() == automaton.query_len();
+
+                    // the postings are decoded while iterated
+                    for di in doc_indexes.iter() {
+                        // postings removed by a more recent segment are outdated
+                        if word_index.is_removed(si, &di) { continue }
+
+                        let attribute = di.attribute;
+                        if self.searchable_attrs.as_ref().map_or(true, |r| r.contains(&attribute)) {
+                            let match_ = Match {
+                                query_index: query.query_indexes[iv.index],
+                                distance: distance,
+                                attribute: di.attribute,
+                                word_index: di.word_index,
+                                is_exact: is_exact,
+                                char_index: di.char_index,
+                                char_length: di.char_length,
+                            };
+
+                            if query.excluded[iv.index] {
+                                excluded_matches.push((di.document_id, match_));
+                            } else {
+                                matches.push((di.document_id, match_));
+                            }
+                        }
                     }
                 }
             }
         }
 
         let total_matches = matches.len();
-        let raw_documents = raw_documents_from_matches(matches);
+        let mut raw_documents = raw_documents_from_matches(matches);
+
+        if !query.phrases.is_empty() {
+            // the documents must contain every phrase of the query
+            raw_documents.retain(|d| {
+                query.phrases.iter().all(|p| {
+                    contains_phrase(p, d.query_index(), d.attribute(), d.word_index())
+                })
+            });
+        }
+
+        if !excluded_matches.is_empty() {
+            // the documents containing any of the excluded words are removed
+            let excluded: HashSet<_> = raw_documents_from_matches(excluded_matches)
+                .into_iter()
+                .filter(|d| {
+                    query.excluded_phrases.iter().any(|p| {
+                        contains_phrase(p, d.query_index(), d.attribute(), d.word_index())
+                    })
+                })
+                .map(|d| d.id)
+                .collect();
+
+            raw_documents.retain(|d| !excluded.contains(&d.id));
+        }
 
         info!("{} total documents to classify", raw_documents.len());
         info!("{} total matches to classify", total_matches);
 
-        raw_documents
+        Ok(raw_documents)
     }
 }
 
-impl<'c, I, FI> QueryBuilder<'c, I, FI>
-where I: Deref<Target=Index>,
-      FI: Fn(DocumentId) -> bool,
+impl<'c, S, FI> QueryBuilder<'c, S, FI>
+where S: Store,
+      FI: Filter,
 {
-    pub fn query(self, query: &str, range: Range<usize>) -> Vec<Document> {
+    pub fn query(self, query: &str, range: Range<usize>) -> Result<SearchResult, S::Error> {
         // We delegate the filter work to the distinct query builder,
         // specifying a distinct rule that has no effect.
         if self.filter.is_some() {
@@ -148,60 +498,107 @@ where I: Deref<Target=Index>,
         }
 
         let start = Instant::now();
-        let mut documents = self.query_all(query);
+        let documents = self.query_all(query)?;
         info!("query_all took {:.2?}", start.elapsed());
 
-        let mut groups = vec![documents.as_mut_slice()];
+        let total_hits = documents.len();
+        let hits = bucket_sort(&self.criteria, documents, range);
 
-        'criteria: for (ci, criterion) in self.criteria.as_ref().iter().enumerate() {
-            let tmp_groups = mem::replace(&mut groups, Vec::new());
-            let mut documents_seen = 0;
+        Ok(SearchResult { hits, total_hits, exhaustive: true, processing_time: start.elapsed() })
+    }
 
-            for group in tmp_groups {
-                info!("criterion {}, documents group of size {}", ci, group.len());
+    /// Returns the documents in the range like `query` along with the ids of all
+    /// the documents matching the query, every document must be given to the filter.
+    pub fn query_with_documents_ids(
+        self,
+        query: &str,
+        range: Range<usize>,
+    ) -> Result<(SearchResult, SetBuf<DocumentId>), S::Error>
+    {
+        let start = Instant::now();
+        let mut documents = self.query_all(query)?;
+        info!("query_all took {:.2?}", start.elapsed());
 
-                // if this group does not overlap with the requested range,
-                // push it without sorting and splitting it
-                if documents_seen + group.len() < range.start {
-                    documents_seen += group.len();
-                    groups.push(group);
-                    continue;
-                }
+        if let Some(filter) = &self.filter {
+            documents.retain(|d| filter.accept(d.id));
+        }
 
-                let start = Instant::now();
-                group.par_sort_unstable_by(|a, b| criterion.evaluate(a, b));
-                info!("criterion {} sort took {:.2?}", ci, start.elapsed());
+        // the raw documents are ordered by id
+        let ids = documents.iter().map(|d| d.id).collect();
+        let ids = SetBuf::new_unchecked(ids);
 
-                for group in group.binary_group_by_mut(|a, b| criterion.eq(a, b)) {
-                    documents_seen += group.len();
-                    groups.push(group);
+        let total_hits = documents.len();
+        let hits = bucket_sort(&self.criteria, documents, range);
+        let processing_time = start.elapsed();
 
-                    // we have sort enough documents if the last document sorted is after
-                    // the end of the requested range, we can continue to the next criterion
-                    if documents_seen >= range.end { continue 'criteria }
-                }
+        Ok((SearchResult { hits, total_hits, exhaustive: true, processing_time }, ids))
+    }
+}
+
+/// Sorts the documents by the criteria, only the groups of documents
+/// that overlap with the requested range are sorted.
+fn bucket_sort(
+    criteria: &Criteria,
+    mut documents: Vec<RawDocument>,
+    range: Range<usize>,
+) -> Vec<Document>
+{
+    let mut groups = vec![documents.as_mut_slice()];
+
+    'criteria: for (ci, criterion) in criteria.as_ref().iter().enumerate() {
+        let tmp_groups = mem::replace(&mut groups, Vec::new());
+        let mut documents_seen = 0;
+
+        for group in tmp_groups {
+            info!("criterion {}, documents group of size {}", ci, group.len());
+
+            // if this group does not overlap with the requested range,
+            // push it without sorting and splitting it
+            if documents_seen + group.len() < range.start {
+                documents_seen += group.len();
+                groups.push(group);
+                continue;
             }
-        }
 
-        let offset = cmp::min(documents.len(), range.start);
-        let iter = documents.into_iter().skip(offset).take(range.len());
-        iter.map(|d| Document::from_raw(&d)).collect()
+            let start = Instant::now();
+            group.par_sort_unstable_by(|a, b| criterion.evaluate(a, b));
+            info!("criterion {} sort took {:.2?}", ci, start.elapsed());
+
+            for group in group.binary_group_by_mut(|a, b| criterion.eq(a, b)) {
+                documents_seen += group.len();
+                groups.push(group);
+
+                // we have sort enough documents if the last document sorted is after
+                // the end of the requested range, we can continue to the next criterion
+                if documents_seen >= range.end { continue 'criteria }
+            }
+        }
     }
+
+    let offset = cmp::min(documents.len(), range.start);
+    let iter = documents.into_iter().skip(offset).take(range.len());
+    iter.map(|d| Document::from_raw(&d)).collect()
 }
 
-pub struct DistinctQueryBuilder<'c, I, FI, FD> {
-    inner: QueryBuilder<'c, I, FI>,
+pub struct DistinctQueryBuilder<'c, S, FI, FD> {
+    inner: QueryBuilder<'c, S, FI>,
     function: FD,
     size: usize,
 }
 
-impl<'c, I, FI, FD> DistinctQueryBuilder<'c, I, FI, FD>
+impl<'c, S, FI, FD> DistinctQueryBuilder<'c, S, FI, FD>
 {
-    pub fn with_filter<F>(self, function: F) -> DistinctQueryBuilder<'c, I, F, FD>
+    pub fn with_filter<F>(self, function: F) -> DistinctQueryBuilder<'c, S, F, FD>
     where F: Fn(DocumentId) -> bool,
+    {
+        self.with_custom_filter(function)
+    }
+
+    pub fn with_custom_filter<F>(self, filter: F) -> DistinctQueryBuilder<'c, S, F, FD>
+    where F: Filter,
     {
         DistinctQueryBuilder {
-            inner: self.inner.with_filter(function),
+            inner: self.inner.with_custom_filter(filter),
             function: self.function,
             size: self.size
         }
@@ -210,17 +607,21 @@ impl<'c, I, FI, FD> DistinctQueryBuilder<'c, I, FI, FD>
     pub fn add_searchable_attribute(&mut self, attribute: u16) {
         self.inner.add_searchable_attribute(attribute);
     }
+
+    pub fn set_exhaustive_count(&mut self, exhaustive: bool) {
+        self.inner.set_exhaustive_count(exhaustive);
+    }
 }
 
-impl<'c, I, FI, FD, K> DistinctQueryBuilder<'c, I, FI, FD>
-where I: Deref<Target=Index>,
-      FI: Fn(DocumentId) -> bool,
+impl<'c, S, FI, FD, K> DistinctQueryBuilder<'c, S, FI, FD>
+where S: Store,
+      FI: Filter,
       FD: Fn(DocumentId) -> Option<K>,
       K: Hash + Eq,
 {
-    pub fn query(self, query: &str, range: Range<usize>) -> Vec<Document> {
+    pub fn query(self, query: &str, range: Range<usize>) -> Result<SearchResult, S::Error> {
         let start = Instant::now();
-        let mut documents = self.inner.query_all(query);
+        let mut documents = self.inner.query_all(query)?;
         info!("query_all took {:.2?}", start.elapsed());
 
         let mut groups = vec![documents.as_mut_slice()];
@@ -259,7 +660,7 @@ where I: Deref<Target=Index>,
                         let filter_accepted = match &self.inner.filter {
                             Some(filter) => {
                                 let entry = filter_map.entry(document.id);
-                                *entry.or_insert_with(|| (filter)(document.id))
+                                *entry.or_insert_with(|| filter.accept(document.id))
                             },
                             None => true,
                         };
@@ -295,6 +696,50 @@ where I: Deref<Target=Index>,
             }
         }
 
+        // the distinct rule keeps the same number of documents whatever their order,
+        // the documents not evaluated while sorting are counted only if exhaustive
+        let mut total_map = DistinctMap::new(self.size);
+        let mut total_seen = BufferedDistinctMap::new(&mut total_map);
+        let mut evaluated = 0;
+
+        for document in &documents {
+            let already_evaluated = match &self.inner.filter {
+                Some(_) => filter_map.contains_key(&document.id),
+                None => key_cache.contains_key(&document.id),
+            };
+
+            if !already_evaluated && !self.inner.exhaustive_count { continue }
+            evaluated += 1;
+
+            let filter_accepted = match &self.inner.filter {
+                Some(filter) => {
+                    let entry = filter_map.entry(document.id);
+                    *entry.or_insert_with(|| filter.accept(document.id))
+                },
+                None => true,
+            };
+
+            if filter_accepted {
+                let entry = key_cache.entry(document.id);
+                let key = entry.or_insert_with(|| (self.function)(document.id).map(Rc::new));
+
+                match key.clone() {
+                    Some(key) => total_seen.register(key),
+                    None => total_seen.register_without_key(),
+                };
+            }
+        }
+
+        let accepted = total_seen.len();
+        let exhaustive = evaluated == documents.len();
+        let total_hits = if exhaustive || evaluated == 0 {
+            accepted
+        } else {
+            // the documents not evaluated are accepted in the same proportion
+            let not_evaluated = documents.len() - evaluated;
+            accepted + (not_evaluated as f64 * accepted as f64 / evaluated as f64).round() as usize
+        };
+
         let mut out_documents = Vec::with_capacity(range.len());
         let mut seen = BufferedDistinctMap::new(&mut distinct_map);
 
@@ -318,6 +763,162 @@ where I: Deref<Target=Index>,
             }
         }
 
-        out_documents
+        let processing_time = start.elapsed();
+        Ok(SearchResult { hits: out_documents, total_hits, exhaustive, processing_time })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fst::Automaton;
+
+    fn word(word: &str) -> QueryWord {
+        QueryWord { word: word.to_owned(), prefix: false }
+    }
+
+    fn prefix(word: &str) -> QueryWord {
+        QueryWord { word: word.to_owned(), prefix: true }
+    }
+
+    #[test]
+    fn parse_phrases() {
+        let trees = parse_query("I love \"New York\" city");
+        let expected = vec![
+            QueryTree::Word(word("i")),
+            QueryTree::Word(word("love")),
+            QueryTree::Phrase(vec![(word("new"), 0), (word("york"), 1)]),
+            QueryTree::Word(prefix("city")),
+        ];
+        assert_eq!(trees, expected);
+
+        // the separators of a phrase are kept in the offsets
+        let trees = parse_query("\"new-york city\" \"the\" \"san fran");
+        let expected = vec![
+            QueryTree::Phrase(vec![(word("new"), 0), (word("york"), 8), (word("city"), 9)]),
+            QueryTree::Word(word("the")),
+            QueryTree::Phrase(vec![(word("san"), 0), (prefix("fran"), 1)]),
+        ];
+        assert_eq!(trees, expected);
+    }
+
+    #[test]
+    fn parse_unquoted_words() {
+        // the queries without any operator are split like the indexed texts
+        let queries = ["hello world", "New-York city  ", "l'avion, 2019 東京", "  rust;golang"];
+
+        for query in &queries {
+            let trees = parse_query(query);
+            let ends_with_space = query.ends_with(' ');
+
+            let tokens: Vec<_> = Tokenizer::new(query).collect();
+            let expected: Vec<_> = tokens.iter().enumerate().map(|(i, token)| {
+                let last = i == tokens.len() - 1;
+                let cjk = token.word.chars().all(is_cjk);
+                let prefix = last && !ends_with_space && !cjk;
+                QueryTree::Word(QueryWord { word: token.word.to_lowercase(), prefix })
+            }).collect();
+
+            assert_eq!(trees, expected);
+        }
+    }
+
+    #[test]
+    fn parse_operators() {
+        let trees = parse_query("jaguar -car -\"formula one\" ");
+        let expected = vec![
+            QueryTree::Word(word("jaguar")),
+            QueryTree::Not(vec![(word("car"), 0)]),
+            QueryTree::Not(vec![(word("formula"), 0), (word("one"), 1)]),
+        ];
+        assert_eq!(trees, expected);
+
+        let trees = parse_query("Rust OR golang OR c-lang or");
+        let expected = vec![
+            QueryTree::Or(vec![word("rust"), word("golang"), word("c"), word("lang")]),
+            QueryTree::Word(prefix("or")),
+        ];
+        assert_eq!(trees, expected);
+
+        // the whole operands are joined, on both sides of the operator
+        let trees = parse_query("a OR foo-bar");
+        let expected = vec![QueryTree::Or(vec![word("a"), word("foo"), prefix("bar")])];
+        assert_eq!(trees, expected);
+
+        let trees = parse_query("foo-bar OR a baz ");
+        let expected = vec![
+            QueryTree::Or(vec![word("foo"), word("bar"), word("a")]),
+            QueryTree::Word(word("baz")),
+        ];
+        assert_eq!(trees, expected);
+
+        // the operators without words to join are ordinary words
+        let trees = parse_query("OR rust OR \"go lang\"");
+        let expected = vec![
+            QueryTree::Word(word("or")),
+            QueryTree::Word(word("rust")),
+            QueryTree::Word(word("or")),
+            QueryTree::Phrase(vec![(word("go"), 0), (word("lang"), 1)]),
+        ];
+        assert_eq!(trees, expected);
+    }
+
+    #[test]
+    fn query_indexes() {
+        let query = generate_automatons("rust OR golang -java \"web server\"");
+        assert_eq!(query.query_indexes, &[0, 0, 0, 1, 2]);
+        assert_eq!(query.excluded, &[false, false, true, false, false]);
+        assert_eq!(query.phrases, &[Phrase { words: vec![(1, 0), (2, 1)] }]);
+        assert_eq!(query.excluded_phrases, &[Phrase { words: vec![(0, 0)] }]);
+    }
+
+    fn accepts(automaton: &DfaExt, word: &str) -> bool {
+        let mut state = automaton.start();
+        for byte in word.bytes() {
+            state = automaton.accept(&state, byte);
+        }
+        automaton.is_match(&state)
+    }
+
+    #[test]
+    fn exact_excluded_words() {
+        let query = generate_automatons("jaguar -formula -car");
+        assert_eq!(query.excluded, &[false, true, true]);
+
+        // the typos and the prefixes of the excluded words do not match
+        let excluded = &query.automatons[1];
+        assert!(accepts(excluded, "formula"));
+        assert!(!accepts(excluded, "formule"));
+        assert!(!accepts(excluded, "formulas"));
+
+        let excluded = &query.automatons[2];
+        assert!(accepts(excluded, "car"));
+        assert!(!accepts(excluded, "cars"));
+
+        // the words that are not excluded still accept typos
+        assert!(accepts(&query.automatons[0], "jaguars"));
+    }
+
+    #[test]
+    fn phrase_positions() {
+        let phrase = Phrase { words: vec![(0, 0), (1, 1)] };
+
+        // "new" and "york" are consecutive in the second attribute only
+        let query_index = &[0, 0, 1, 1];
+        let attribute   = &[0, 1, 0, 1];
+        let word_index  = &[0, 4, 2, 5];
+        assert!(contains_phrase(&phrase, query_index, attribute, word_index));
+
+        // "new" and "york" are consecutive but not in the same attribute
+        let query_index = &[0, 1];
+        let attribute   = &[0, 1];
+        let word_index  = &[4, 5];
+        assert!(!contains_phrase(&phrase, query_index, attribute, word_index));
+
+        // "york" comes before "new"
+        let query_index = &[0, 1];
+        let attribute   = &[0, 0];
+        let word_index  = &[5, 4];
+        assert!(!contains_phrase(&phrase, query_index, attribute, word_index));
     }
 }
@@ -0,0 +1,483 @@
+use std::error::Error;
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::sync::Arc;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use fst::{Map, Streamer};
+use fst::raw::Fst;
+use sdset::{Set, SetBuf};
+
+use crate::data::DocIds;
+use crate::shared_data_cursor::{SharedDataCursor, FromSharedDataCursor};
+use crate::write_to_bytes::WriteToBytes;
+use crate::postings::Postings;
+use crate::{DocumentId, DocIndex, Index, IndexBuilder};
+
+/// The number of segments above which the index must be compacted.
+const MAX_SEGMENTS: usize = 8;
+
+/// Gives access to the segments of a word index and to the postings
+/// of their words, the postings are stored apart and loaded lazily.
+pub trait Store {
+    type Error;
+
+    fn word_index(&self) -> &SegmentedIndex;
+
+    fn word_indexes(&self, segment: u64, word: &[u8]) -> Result<Option<Postings>, Self::Error>;
+}
+
+/// An immutable part of a `SegmentedIndex`, it contains the dictionary of the words
+/// of an update and the documents and document attributes it removes from the older segments.
+///
+/// The postings of the words are not part of the segment, they are given by a `Store`.
+pub struct Segment {
+    pub id: u64,
+    pub words: Map,
+    pub removed_documents: DocIds,
+    pub removed_attributes: SetBuf<(DocumentId, u16)>,
+}
+
+impl Segment {
+    /// Returns a segment without any word, it can stand in for a segment that can not be read.
+    pub fn empty(id: u64) -> Segment {
+        Segment {
+            id,
+            words: Map::default(),
+            removed_documents: DocIds::default(),
+            removed_attributes: SetBuf::new_unchecked(Vec::new()),
+        }
+    }
+
+    /// Returns a copy of this segment without its words, to store them apart.
+    pub fn without_words(&self) -> Segment {
+        Segment {
+            id: self.id,
+            words: Map::default(),
+            removed_documents: self.removed_documents.clone(),
+            removed_attributes: self.removed_attributes.clone(),
+        }
+    }
+
+    /// Returns whether this segment removes the given posting of an older segment.
+    pub fn removes(&self, doc_index: &DocIndex) -> bool {
+        let DocIndex { document_id, attribute, .. } = *doc_index;
+
+        self.removed_documents.as_ref().binary_search(&document_id).is_ok() ||
+        self.removed_attributes.binary_search(&(document_id, attribute)).is_ok()
+    }
+
+    /// Calls the function with every word of this segment, in lexicographic order.
+    pub fn for_each_word<F: FnMut(&[u8])>(&self, mut f: F) {
+        let mut stream = self.words.stream();
+        while let Some((word, _)) = stream.next() {
+            f(word);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.words.len() +
+        self.removed_documents.as_ref().len() +
+        self.removed_attributes.len()
+    }
+}
+
+fn corrupted(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl FromSharedDataCursor for Segment {
+    type Error = Box<Error>;
+
+    /// The checksum of the segment is verified before anything is read from it.
+    fn from_shared_data_cursor(cursor: &mut SharedDataCursor) -> Result<Segment, Self::Error> {
+        let len = cursor.read_u64::<LittleEndian>()? as usize;
+        if cursor.fill_buf()?.len() < len.saturating_add(4) {
+            return Err(corrupted("truncated segment").into())
+        }
+
+        let data = cursor.extract(len);
+        let checksum = cursor.read_u32::<LittleEndian>()?;
+        if crc32fast::hash(&data) != checksum {
+            return Err(corrupted("segment checksum mismatch").into())
+        }
+
+        let mut cursor = SharedDataCursor::from_shared_bytes(data.bytes, data.offset, data.len);
+        let cursor = &mut cursor;
+
+        let len = cursor.read_u64::<LittleEndian>()? as usize;
+        let data = cursor.extract(len);
+
+        let fst = Fst::from_shared_bytes(data.bytes, data.offset, data.len)?;
+        let words = Map::from(fst);
+
+        let removed_documents = DocIds::from_shared_data_cursor(cursor)?;
+
+        let len = cursor.read_u64::<LittleEndian>()? as usize;
+        let mut removed_attributes = Vec::with_capacity(len);
+        for _ in 0..len {
+            let document_id = cursor.read_u64::<LittleEndian>().map(DocumentId)?;
+            let attribute = cursor.read_u16::<LittleEndian>()?;
+            removed_attributes.push((document_id, attribute));
+        }
+        let removed_attributes = SetBuf::new_unchecked(removed_attributes);
+
+        // the segment id is not serialized, it is known by the owner of the segment
+        Ok(Segment { id: 0, words, removed_documents, removed_attributes })
+    }
+}
+
+impl WriteToBytes for Segment {
+    /// The segment is written followed by the checksum of its bytes.
+    fn write_to_bytes(&self, bytes: &mut Vec<u8>) {
+        let mut data = Vec::new();
+
+        let slice = self.words.as_fst().as_bytes();
+        let len = slice.len() as u64;
+        let _ = data.write_u64::<LittleEndian>(len);
+        data.extend_from_slice(slice);
+
+        self.removed_documents.write_to_bytes(&mut data);
+
+        let len = self.removed_attributes.len() as u64;
+        let _ = data.write_u64::<LittleEndian>(len);
+        for (DocumentId(document_id), attribute) in self.removed_attributes.iter() {
+            let _ = data.write_u64::<LittleEndian>(*document_id);
+            let _ = data.write_u16::<LittleEndian>(*attribute);
+        }
+
+        let _ = bytes.write_u64::<LittleEndian>(data.len() as u64);
+        bytes.extend_from_slice(&data);
+        let _ = bytes.write_u32::<LittleEndian>(crc32fast::hash(&data));
+    }
+}
+
+/// Memory maps the words of a segment written in a file,
+/// the file must not be modified while the words are used.
+pub unsafe fn mmap_words<P: AsRef<Path>>(path: P) -> io::Result<Map> {
+    Map::from_path(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+/// A word index made of immutable segments searched together, every update
+/// appends a new segment instead of rebuilding the whole index.
+///
+/// Segments are ordered from the oldest to the most recent one, the postings of a segment
+/// are hidden by the documents and document attributes removed by the more recent ones.
+#[derive(Default, Clone)]
+pub struct SegmentedIndex {
+    segments: Vec<Arc<Segment>>,
+}
+
+impl SegmentedIndex {
+    pub fn from_segments(mut segments: Vec<Segment>) -> SegmentedIndex {
+        segments.sort_unstable_by_key(|s| s.id);
+        let segments = segments.into_iter().map(Arc::new).collect();
+        SegmentedIndex { segments }
+    }
+
+    pub fn segments(&self) -> &[Arc<Segment>] {
+        &self.segments
+    }
+
+    /// Returns the id the next segment will have, its postings must be stored under this id.
+    pub fn next_segment_id(&self) -> u64 {
+        self.segments.last().map_or(0, |s| s.id + 1)
+    }
+
+    /// Returns whether a posting of the given segment is removed by a more recent segment.
+    pub fn is_removed(&self, segment: usize, doc_index: &DocIndex) -> bool {
+        self.segments[segment + 1..].iter().any(|s| s.removes(doc_index))
+    }
+
+    /// Returns the number of distinct words in the segments, a word
+    /// present in multiple segments is only counted once.
+    pub fn number_of_words(&self) -> usize {
+        let mut op_builder = fst::map::OpBuilder::new();
+        for segment in &self.segments {
+            op_builder.push(&segment.words);
+        }
+
+        let mut stream = op_builder.r#union();
+        let mut count = 0;
+        while let Some(_) = stream.next() { count += 1 }
+
+        count
+    }
+
+    /// Returns a new index with an additional segment containing the new words,
+    /// the removed documents and attributes are hidden from the previous segments.
+    pub fn push(
+        &self,
+        words: Map,
+        removed_documents: &Set<DocumentId>,
+        removed_attributes: SetBuf<(DocumentId, u16)>,
+    ) -> SegmentedIndex
+    {
+        let segment = Segment {
+            id: self.next_segment_id(),
+            words,
+            removed_documents: DocIds::new(removed_documents),
+            removed_attributes,
+        };
+
+        let mut segments = self.segments.clone();
+        segments.push(Arc::new(segment));
+
+        SegmentedIndex { segments }
+    }
+
+    /// Returns a new index made of a single segment, replacing all the existing ones.
+    pub fn replace(&self, words: Map) -> SegmentedIndex {
+        let segment = Segment {
+            id: self.next_segment_id(),
+            words,
+            removed_documents: DocIds::default(),
+            removed_attributes: SetBuf::new_unchecked(Vec::new()),
+        };
+
+        SegmentedIndex { segments: vec![Arc::new(segment)] }
+    }
+
+    /// Returns a new index where the segment with the given id uses the given words,
+    /// the words must be the same, only the way they are accessed can change.
+    pub fn replace_words(&self, id: u64, words: Map) -> SegmentedIndex {
+        let mut segments = self.segments.clone();
+
+        if let Some(segment) = segments.iter_mut().find(|s| s.id == id) {
+            let mut new_segment = segment.without_words();
+            new_segment.words = words;
+            *segment = Arc::new(new_segment);
+        }
+
+        SegmentedIndex { segments }
+    }
+
+    pub fn needs_compaction(&self) -> bool {
+        self.segments.len() > MAX_SEGMENTS
+    }
+
+    /// Returns the position of the first segment to merge with the more recent ones,
+    /// a segment is merged if it is not much bigger than all the more recent segments.
+    ///
+    /// The cost of the merges is therefore amortized, like in an LSM tree.
+    pub fn compaction_start(&self) -> usize {
+        let len = self.segments.len();
+        if len < 2 { return 0 }
+
+        let mut start = len - 1;
+        let mut size = self.segments[start].len();
+
+        while start > 0 {
+            let previous = self.segments[start - 1].len();
+            if start < len - 1 && previous > size * 2 { break }
+
+            size += previous;
+            start -= 1;
+        }
+
+        start
+    }
+
+    /// Returns a new index where the segments starting at the given position
+    /// are replaced by a single segment containing their merged words.
+    pub fn compacted(&self, start: usize, words: Map) -> SegmentedIndex {
+        let mut removed_documents = Vec::new();
+        let mut removed_attributes = Vec::new();
+
+        // there is no older segment to remove postings from
+        if start != 0 {
+            for segment in &self.segments[start..] {
+                removed_documents.extend_from_slice(segment.removed_documents.as_ref());
+                removed_attributes.extend_from_slice(&segment.removed_attributes);
+            }
+        }
+
+        removed_documents.sort_unstable();
+        removed_documents.dedup();
+        let removed_documents = SetBuf::new_unchecked(removed_documents);
+
+        removed_attributes.sort_unstable();
+        removed_attributes.dedup();
+
+        let segment = Segment {
+            id: self.next_segment_id(),
+            words,
+            removed_documents: DocIds::new(&removed_documents),
+            removed_attributes: SetBuf::new_unchecked(removed_attributes),
+        };
+
+        let mut segments = self.segments[..start].to_vec();
+        segments.push(Arc::new(segment));
+
+        SegmentedIndex { segments }
+    }
+}
+
+/// Loads the postings of the given segments from the store and merges them into a single
+/// index, the postings removed by a segment are removed from the previous ones.
+pub fn merge_segments<S: Store>(store: &S, segments: &[Arc<Segment>]) -> Result<Index, S::Error> {
+    let mut index = Index::default();
+
+    for segment in segments {
+        let documents = segment.removed_documents.as_ref();
+        if !documents.is_empty() {
+            index = index.remove_documents(documents);
+        }
+
+        if !segment.removed_attributes.is_empty() {
+            index = index.remove_documents_attributes(&segment.removed_attributes);
+        }
+
+        let mut builder = IndexBuilder::new();
+        let mut stream = segment.words.stream();
+        while let Some((word, _)) = stream.next() {
+            if let Some(postings) = store.word_indexes(segment.id, word)? {
+                builder.insert(word, &postings.to_set_buf()).unwrap();
+            }
+        }
+
+        index = index.r#union(&builder.build());
+    }
+
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use fst::IntoStreamer;
+    use crate::postings::{write_postings, PostingsEncoding};
+
+    #[derive(Default)]
+    struct MemoryStore {
+        word_index: SegmentedIndex,
+        postings: HashMap<(u64, Vec<u8>), SetBuf<DocIndex>>,
+    }
+
+    impl MemoryStore {
+        fn push(&mut self, words: &[(&str, &[DocIndex])], documents: &[u64]) {
+            let id = self.word_index.next_segment_id();
+            let mut builder = fst::MapBuilder::memory();
+            for (word, indexes) in words {
+                builder.insert(word, 0).unwrap();
+                let indexes = SetBuf::new_unchecked(indexes.to_vec());
+                self.postings.insert((id, word.as_bytes().to_vec()), indexes);
+            }
+            let map = Map::from_bytes(builder.into_inner().unwrap()).unwrap();
+
+            let documents: Vec<_> = documents.iter().cloned().map(DocumentId).collect();
+            let documents = SetBuf::new_unchecked(documents);
+            let attributes = SetBuf::new_unchecked(Vec::new());
+
+            self.word_index = self.word_index.push(map, &documents, attributes);
+        }
+    }
+
+    impl Store for MemoryStore {
+        type Error = ();
+
+        fn word_index(&self) -> &SegmentedIndex {
+            &self.word_index
+        }
+
+        fn word_indexes(&self, segment: u64, word: &[u8]) -> Result<Option<Postings>, ()> {
+            let indexes = match self.postings.get(&(segment, word.to_vec())) {
+                Some(indexes) => indexes,
+                None => return Ok(None),
+            };
+
+            let mut bytes = Vec::new();
+            write_postings(indexes, PostingsEncoding::Raw, &mut bytes);
+            Ok(Some(Postings::from_bytes(bytes).unwrap()))
+        }
+    }
+
+    fn doc_index(document_id: u64, attribute: u16) -> DocIndex {
+        DocIndex {
+            document_id: DocumentId(document_id),
+            attribute,
+            word_index: 0,
+            char_index: 0,
+            char_length: 0,
+        }
+    }
+
+    #[test]
+    fn corrupted_segment() {
+        let mut store = MemoryStore::default();
+        store.push(&[("hello", &[doc_index(0, 0)])], &[1]);
+
+        let mut bytes = store.word_index().segments()[0].into_bytes();
+        assert!(Segment::from_bytes(bytes.clone()).is_ok());
+
+        let last = bytes.len() - 5;
+        bytes[last] ^= 1;
+        assert!(Segment::from_bytes(bytes.clone()).is_err());
+
+        bytes.truncate(last);
+        assert!(Segment::from_bytes(bytes).is_err());
+    }
+
+    #[test]
+    fn removed_postings() {
+        let a = doc_index(0, 0);
+        let b = doc_index(1, 0);
+
+        let mut store = MemoryStore::default();
+        store.push(&[("hello", &[a, b])], &[]);
+        store.push(&[], &[0]);
+
+        let word_index = store.word_index();
+        assert!(word_index.is_removed(0, &a));
+        assert!(!word_index.is_removed(0, &b));
+
+        let merged = merge_segments(&store, word_index.segments()).unwrap();
+        let postings = merged.map.get("hello").map(|i| &merged.indexes[i as usize]);
+        assert_eq!(postings, Some(&[b][..]));
+    }
+
+    #[test]
+    fn number_of_words() {
+        let a = doc_index(0, 0);
+        let b = doc_index(1, 0);
+
+        let mut store = MemoryStore::default();
+        assert_eq!(store.word_index().number_of_words(), 0);
+
+        store.push(&[("hello", &[a]), ("world", &[a])], &[]);
+        store.push(&[("hello", &[b]), ("kiwi", &[b])], &[]);
+        assert_eq!(store.word_index().number_of_words(), 3);
+    }
+
+    #[test]
+    fn compaction_keeps_postings() {
+        let mut store = MemoryStore::default();
+        for i in 0..20 {
+            store.push(&[("hello", &[doc_index(i, 0)])], &[i]);
+
+            let word_index = store.word_index().clone();
+            if word_index.needs_compaction() {
+                let start = word_index.compaction_start();
+                let merged = merge_segments(&store, &word_index.segments()[start..]).unwrap();
+
+                let id = word_index.next_segment_id();
+                let mut stream = merged.into_stream();
+                while let Some((word, indexes)) = stream.next() {
+                    let indexes = SetBuf::new_unchecked(indexes.to_vec());
+                    store.postings.insert((id, word.to_vec()), indexes);
+                }
+
+                let words = Map::from_bytes(merged.map.as_fst().as_bytes().to_vec()).unwrap();
+                store.word_index = word_index.compacted(start, words);
+            }
+        }
+
+        let word_index = store.word_index();
+        assert!(!word_index.needs_compaction());
+
+        let merged = merge_segments(&store, word_index.segments()).unwrap();
+        let postings = merged.map.get("hello").map(|i| merged.indexes[i as usize].len());
+        assert_eq!(postings, Some(20));
+    }
+}
@@ -0,0 +1,461 @@
+use std::sync::Arc;
+
+use hashbrown::HashMap;
+use meilidb_core::DocumentId;
+use sdset::{Set, SetBuf};
+
+use crate::{SchemaAttr, RankedMap, FacetMap};
+use crate::serde::{extract_external_id, compute_document_id};
+use crate::serde::{Serializer, SerializerError};
+use crate::indexer::Indexer;
+use super::{Error, RawIndex, WriteBatch, UpdateEvent, UpdateType};
+use super::{NUMBER_OF_DOCUMENTS_KEY, external_id_key};
+
+/// Returns a short JSON representation of the document to be used in error messages.
+fn document_excerpt<D: serde::Serialize>(document: &D) -> String {
+    const MAX_LEN: usize = 100;
+
+    let mut excerpt = serde_json::to_string(document).unwrap_or_default();
+    if excerpt.len() > MAX_LEN {
+        let mut end = MAX_LEN;
+        while !excerpt.is_char_boundary(end) { end -= 1 }
+        excerpt.truncate(end);
+        excerpt.push_str("...");
+    }
+    excerpt
+}
+
+/// Infers the identifier from the first fields of a document, an `id` field
+/// is prefered, otherwise the first field whose name ends with `_id` is used.
+fn infer_identifier<D: serde::Serialize>(document: &D) -> Result<Option<String>, Error> {
+    let names: Vec<String> = match serde_json::to_value(document)? {
+        serde_json::Value::Object(map) => map.into_iter().map(|(k, _)| k).collect(),
+        _ => return Ok(None),
+    };
+
+    if names.iter().any(|name| name == "id") {
+        return Ok(Some(String::from("id")))
+    }
+
+    Ok(names.into_iter().find(|name| name.ends_with("_id")))
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum AdditionMode {
+    Replace,
+    Partial,
+}
+
+pub struct DocumentsAddition {
+    inner: RawIndex,
+    mode: AdditionMode,
+    documents: HashMap<DocumentId, Vec<SchemaAttr>>,
+    external_ids: HashMap<DocumentId, String>,
+    inferred_identifier: Option<String>,
+    batch: WriteBatch,
+    indexer: Indexer,
+    ranked_map: RankedMap,
+    facet_map: FacetMap,
+}
+
+impl DocumentsAddition {
+    pub fn from_raw(inner: RawIndex) -> DocumentsAddition {
+        DocumentsAddition::with_mode(inner, AdditionMode::Replace)
+    }
+
+    pub fn partial_from_raw(inner: RawIndex) -> DocumentsAddition {
+        DocumentsAddition::with_mode(inner, AdditionMode::Partial)
+    }
+
+    fn with_mode(inner: RawIndex, mode: AdditionMode) -> DocumentsAddition {
+        DocumentsAddition {
+            inner,
+            mode,
+            documents: HashMap::new(),
+            external_ids: HashMap::new(),
+            inferred_identifier: None,
+            batch: WriteBatch::new(),
+            indexer: Indexer::new(),
+            ranked_map: RankedMap::default(),
+            facet_map: FacetMap::default(),
+        }
+    }
+
+    pub fn update_document<D>(&mut self, document: D) -> Result<(), Error>
+    where D: serde::Serialize,
+    {
+        let schema = self.inner.schema();
+
+        let identifier = schema.identifier().map(ToOwned::to_owned);
+        let identifier = match identifier.or_else(|| self.inferred_identifier.clone()) {
+            Some(identifier) => identifier,
+            None => match infer_identifier(&document)? {
+                Some(identifier) => {
+                    self.inferred_identifier = Some(identifier.clone());
+                    identifier
+                },
+                None => {
+                    let document = document_excerpt(&document);
+                    return Err(Error::IdentifierNotInferred { document })
+                },
+            },
+        };
+
+        let external_id = match extract_external_id(&identifier, &document) {
+            Ok(Some(id)) => id,
+            Ok(None) => {
+                let document = document_excerpt(&document);
+                return Err(Error::MissingDocumentId { identifier, document })
+            },
+            Err(SerializerError::InvalidDocumentIdType { type_name }) => {
+                let document = document_excerpt(&document);
+                return Err(Error::InvalidDocumentId { identifier, type_name, document })
+            },
+            Err(e) => return Err(Error::from(e)),
+        };
+
+        let document_id = compute_document_id(&external_id);
+
+        // two different external ids must never share the same internal id
+        let known_id = match self.external_ids.get(&document_id) {
+            Some(known_id) => Some(known_id.clone()),
+            None => self.inner.external_id(document_id)?,
+        };
+
+        if let Some(known_id) = known_id {
+            if known_id != external_id {
+                return Err(Error::DocumentIdCollision(known_id, external_id))
+            }
+        }
+
+        let mut batch = WriteBatch::new();
+        let mut indexer = Indexer::from_schema(&schema);
+        let mut ranked_map = RankedMap::default();
+        let mut facet_map = FacetMap::default();
+        let mut attributes = Vec::new();
+
+        let serializer = Serializer {
+            schema: &schema,
+            batch: &mut batch,
+            indexer: &mut indexer,
+            ranked_map: &mut ranked_map,
+            facet_map: &mut facet_map,
+            attributes: &mut attributes,
+            document_id,
+        };
+
+        document.serialize(serializer)?;
+
+        batch.set(external_id_key(document_id), external_id.as_str());
+        self.external_ids.insert(document_id, external_id);
+
+        attributes.sort_unstable();
+        attributes.dedup();
+
+        // the same document can be present multiple times in an addition,
+        // the last version replaces or is merged with the previous ones
+        if let Some(previous) = self.documents.get_mut(&document_id) {
+            match self.mode {
+                AdditionMode::Replace => {
+                    self.batch.forget_document(document_id);
+                    self.indexer.retain(|x| x.document_id != document_id);
+                    self.ranked_map.retain(|(id, _), _| *id != document_id);
+                    self.facet_map.remove_documents(Set::new_unchecked(&[document_id]));
+                    previous.clear();
+                },
+                AdditionMode::Partial => {
+                    self.indexer.retain(|x| {
+                        x.document_id != document_id ||
+                        attributes.binary_search(&SchemaAttr(x.attribute)).is_err()
+                    });
+                    for attr in &attributes {
+                        self.ranked_map.remove(&(document_id, *attr));
+                    }
+                    let pairs: Vec<_> = attributes.iter().map(|a| (document_id, a.0)).collect();
+                    self.facet_map.remove_documents_attributes(Set::new_unchecked(&pairs));
+                },
+            }
+        }
+
+        let document_attributes = self.documents.entry(document_id).or_insert_with(Vec::new);
+        document_attributes.extend(attributes);
+        document_attributes.sort_unstable();
+        document_attributes.dedup();
+
+        self.batch.extend(batch);
+        self.indexer.extend(indexer);
+        self.ranked_map.extend(ranked_map);
+        self.facet_map.extend(facet_map);
+
+        Ok(())
+    }
+
+    fn update_type(&self) -> UpdateType {
+        let number = self.documents.len();
+        match self.mode {
+            AdditionMode::Replace => UpdateType::DocumentsAddition { number },
+            AdditionMode::Partial => UpdateType::DocumentsPartialAddition { number },
+        }
+    }
+
+    pub fn finalize(self) -> Result<(), Error> {
+        let inner = self.inner.clone();
+        let update_type = self.update_type();
+
+        let result = self.commit();
+
+        let (truncated_tokens, error) = match &result {
+            Ok(truncated_tokens) => (*truncated_tokens, None),
+            Err(e) => (0, Some(e.to_string())),
+        };
+        let event = UpdateEvent { update_id: None, update_type, truncated_tokens, error };
+        inner.updates.notify(event);
+
+        result.map(drop)
+    }
+
+    /// Applies the addition, returns the number of tokens that were not indexed.
+    pub(crate) fn commit(self) -> Result<usize, Error> {
+        let _lock = self.inner.update_lock.lock().unwrap();
+
+        let index = self.inner.word_index();
+        let mut ranked_map = RankedMap::clone(&self.inner.ranked_map());
+        let mut facet_map = FacetMap::clone(&self.inner.facet_map());
+        let mut batch = WriteBatch::new();
+
+        // the identifier inferred from the first document is persisted
+        // along with the documents, another addition could have inferred it first
+        let new_schema = match self.inferred_identifier {
+            Some(ref identifier) => {
+                let schema = self.inner.schema();
+                match schema.identifier() {
+                    Some(current) if current != identifier.as_str() => {
+                        return Err(Error::SchemaDiffer)
+                    },
+                    Some(_) => None,
+                    None => {
+                        let schema = schema.with_identifier(identifier.as_str());
+                        let mut schema_bytes = Vec::new();
+                        schema.write_to_bin(&mut schema_bytes)?;
+                        batch.set("schema", schema_bytes);
+                        Some(schema)
+                    },
+                }
+            },
+            None => None,
+        };
+
+        // the previous versions of the documents are removed from the stores
+        // before the new versions are written, the ranked map is the current
+        // one as it could have been modified since the creation of this addition
+        let (removed_documents, removed_attributes) = match self.mode {
+            AdditionMode::Replace => {
+                let mut ids: Vec<_> = self.documents.keys().cloned().collect();
+                ids.sort_unstable();
+                let ids = SetBuf::new_unchecked(ids);
+
+                for id in ids.iter() {
+                    for result in self.inner.get_document_fields(*id) {
+                        let (_, attr, _) = result?;
+                        batch.del_document_attribute(*id, attr);
+                    }
+                }
+
+                ranked_map.retain(|(id, _), _| ids.binary_search(id).is_err());
+                facet_map.remove_documents(&ids);
+                (ids, SetBuf::new_unchecked(Vec::new()))
+            },
+            AdditionMode::Partial => {
+                let mut pairs = Vec::new();
+                for (id, attributes) in &self.documents {
+                    pairs.extend(attributes.iter().map(|attr| (*id, attr.0)));
+                }
+                pairs.sort_unstable();
+                let pairs = SetBuf::new_unchecked(pairs);
+
+                ranked_map.retain(|(id, attr), _| pairs.binary_search(&(*id, attr.0)).is_err());
+                facet_map.remove_documents_attributes(&pairs);
+                (SetBuf::new_unchecked(Vec::new()), pairs)
+            },
+        };
+
+        // the new postings are written in a new segment that
+        // hides the removed postings of the previous segments
+        let truncated_tokens = self.indexer.truncated_tokens();
+        let delta_index = self.indexer.build();
+        let encoding = self.inner.postings_encoding();
+        batch.set_segment_postings(index.next_segment_id(), &delta_index, encoding);
+        let new_index = index.push(delta_index.map, &removed_documents, removed_attributes);
+        let new_index = Arc::from(new_index);
+
+        ranked_map.extend(self.ranked_map);
+        let ranked_map = Arc::new(ranked_map);
+
+        facet_map.extend(self.facet_map);
+        let facet_map = Arc::new(facet_map);
+
+        // the documents without an external id are not known yet
+        let mut number_of_documents = self.inner.number_of_documents();
+        for id in self.documents.keys() {
+            if self.inner.external_id(*id)?.is_none() {
+                number_of_documents += 1;
+            }
+        }
+        batch.set(NUMBER_OF_DOCUMENTS_KEY, bincode::serialize(&number_of_documents)?);
+
+        batch.extend(self.batch);
+
+        self.inner.update(batch, new_index, ranked_map, facet_map)?;
+        self.inner.number_of_documents.store(Arc::new(number_of_documents));
+
+        if let Some(schema) = new_schema {
+            self.inner.schema.store(Arc::new(schema));
+        }
+
+        Ok(truncated_tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::{Database, Number};
+    use crate::database::tests::simple_schema;
+    use crate::schema::{SchemaBuilder, STORED, INDEXED};
+
+    #[test]
+    fn replace_and_partial_additions() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+        let index = database.create_index("test".to_string(), simple_schema()).unwrap();
+
+        let mut addition = index.documents_addition();
+        addition.update_document(json!({ "id": 1, "title": "hello world", "rank": 4 })).unwrap();
+        addition.finalize().unwrap();
+        let id = index.document_id("1").unwrap().unwrap();
+
+        // the attributes missing from the new version are removed
+        let mut addition = index.documents_addition();
+        addition.update_document(json!({ "id": 1, "title": "goodbye" })).unwrap();
+        addition.finalize().unwrap();
+
+        let document: serde_json::Value = index.document(None, id).unwrap().unwrap();
+        assert_eq!(document, json!({ "id": 1, "title": "goodbye" }));
+        assert!(index.ranked_map().get(&(id, SchemaAttr(2))).is_none());
+        assert!(index.query_builder().query("hello", 0..10).unwrap().hits.is_empty());
+        assert_eq!(index.query_builder().query("goodbye", 0..10).unwrap().hits.len(), 1);
+
+        // only the given attributes are rewritten
+        let mut addition = index.documents_partial_addition();
+        addition.update_document(json!({ "id": 1, "rank": 9 })).unwrap();
+        addition.finalize().unwrap();
+
+        let document: serde_json::Value = index.document(None, id).unwrap().unwrap();
+        assert_eq!(document, json!({ "id": 1, "title": "goodbye", "rank": 9 }));
+        assert_eq!(index.ranked_map().get(&(id, SchemaAttr(2))), Some(&Number::Unsigned(9)));
+        assert_eq!(index.query_builder().query("goodbye", 0..10).unwrap().hits.len(), 1);
+        assert_eq!(index.number_of_documents(), 1);
+    }
+
+    #[test]
+    fn external_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+        let index = database.create_index("test".to_string(), simple_schema()).unwrap();
+
+        let mut addition = index.documents_addition();
+        addition.update_document(json!({ "id": "abc", "title": "hello" })).unwrap();
+        addition.finalize().unwrap();
+
+        let id = index.document_id("abc").unwrap().unwrap();
+        assert_eq!(id, compute_document_id("abc"));
+        assert_eq!(index.external_id(id).unwrap(), Some("abc".to_string()));
+        assert!(index.document_id("abd").unwrap().is_none());
+
+        let document: serde_json::Value = index.document_by_key(None, "abc").unwrap().unwrap();
+        assert_eq!(document, json!({ "id": "abc", "title": "hello" }));
+
+        // the integer 1 and the string "1" are the same document
+        let mut addition = index.documents_addition();
+        addition.update_document(json!({ "id": 1, "title": "hello" })).unwrap();
+        addition.finalize().unwrap();
+
+        let mut addition = index.documents_addition();
+        addition.update_document(json!({ "id": "1", "title": "world" })).unwrap();
+        addition.finalize().unwrap();
+
+        let document: serde_json::Value = index.document_by_key(None, "1").unwrap().unwrap();
+        assert_eq!(document, json!({ "id": "1", "title": "world" }));
+        assert_eq!(index.number_of_documents(), 2);
+
+        // another external id whose internal id is the same one
+        let colliding_id = compute_document_id("def");
+        index.0.inner.insert(external_id_key(colliding_id), "xyz").unwrap();
+        let mut addition = index.documents_addition();
+        match addition.update_document(json!({ "id": "def", "title": "hello" })) {
+            Err(Error::DocumentIdCollision(known, new)) => {
+                assert_eq!((known.as_str(), new.as_str()), ("xyz", "def"));
+            },
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        assert!(index.delete_document_by_key("abc").unwrap());
+        assert!(!index.delete_document_by_key("abc").unwrap());
+        let document: Option<serde_json::Value> = index.document_by_key(None, "abc").unwrap();
+        assert!(document.is_none());
+    }
+
+    #[test]
+    fn identifier_inference_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+
+        let mut builder = SchemaBuilder::with_inferred_identifier();
+        builder.new_attribute("book_id", STORED);
+        builder.new_attribute("id", STORED);
+        builder.new_attribute("title", STORED | INDEXED);
+        let index = database.create_index("test".to_string(), builder.build()).unwrap();
+
+        let mut addition = index.documents_addition();
+        match addition.update_document(json!({ "title": "hello" })) {
+            Err(Error::IdentifierNotInferred { document }) => {
+                assert_eq!(document, r#"{"title":"hello"}"#);
+            },
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        let mut addition = index.documents_addition();
+        match addition.update_document(json!({ "book_id": 1.5, "title": "hello" })) {
+            Err(Error::InvalidDocumentId { identifier, type_name, .. }) => {
+                assert_eq!((identifier.as_str(), type_name), ("book_id", "float"));
+            },
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        // the id field is prefered to the other ones
+        let mut addition = index.documents_addition();
+        addition.update_document(json!({ "book_id": 1, "id": 2, "title": "hello" })).unwrap();
+        addition.finalize().unwrap();
+        assert_eq!(index.schema().identifier(), Some("id"));
+
+        // the schema without the inferred identifier is still the same one
+        let mut builder = SchemaBuilder::with_inferred_identifier();
+        builder.new_attribute("book_id", STORED);
+        builder.new_attribute("id", STORED);
+        builder.new_attribute("title", STORED | INDEXED);
+        database.create_index("test".to_string(), builder.build()).unwrap();
+
+        let mut addition = index.documents_addition();
+        match addition.update_document(json!({ "book_id": 3, "title": "hello" })) {
+            Err(Error::MissingDocumentId { identifier, .. }) => assert_eq!(identifier, "id"),
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        let mut addition = index.documents_addition();
+        match addition.update_document(json!({ "id": [4], "title": "hello" })) {
+            Err(Error::InvalidDocumentId { type_name: "sequence", .. }) => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::sync::Arc;
+
+use meilidb_core::DocumentId;
+use sdset::SetBuf;
+
+use crate::{RankedMap, FacetMap};
+use super::{Error, RawIndex, WriteBatch, UpdateEvent, UpdateType};
+use super::{NUMBER_OF_DOCUMENTS_KEY, external_id_key};
+
+pub struct DocumentsDeletion {
+    inner: RawIndex,
+    documents: Vec<DocumentId>,
+}
+
+impl DocumentsDeletion {
+    pub fn from_raw(inner: RawIndex) -> DocumentsDeletion {
+        DocumentsDeletion {
+            inner,
+            documents: Vec::new(),
+        }
+    }
+
+    pub fn delete_document(&mut self, id: DocumentId) {
+        self.documents.push(id);
+    }
+
+    /// Deletes the document with the given external id,
+    /// returns `false` if no such document exists.
+    pub fn delete_document_by_key(&mut self, external_id: &str) -> Result<bool, Error> {
+        match self.inner.document_id(external_id)? {
+            Some(id) => {
+                self.delete_document(id);
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    pub fn finalize(self) -> Result<(), Error> {
+        let idset = sorted_idset(self.documents);
+        let update_type = UpdateType::DocumentsDeletion { number: idset.len() };
+
+        let result = delete_documents(&self.inner, idset);
+
+        let error = result.as_ref().err().map(ToString::to_string);
+        let event = UpdateEvent { update_id: None, update_type, truncated_tokens: 0, error };
+        self.inner.updates.notify(event);
+
+        result
+    }
+
+    pub(crate) fn commit(self) -> Result<(), Error> {
+        delete_documents(&self.inner, sorted_idset(self.documents))
+    }
+}
+
+fn sorted_idset(mut documents: Vec<DocumentId>) -> SetBuf<DocumentId> {
+    documents.sort_unstable();
+    documents.dedup();
+    SetBuf::new_unchecked(documents)
+}
+
+fn delete_documents(inner: &RawIndex, idset: SetBuf<DocumentId>) -> Result<(), Error> {
+    let _lock = inner.update_lock.lock().unwrap();
+
+    let index = inner.word_index();
+
+    // an empty segment hides the postings of the deleted documents
+    let no_attributes = SetBuf::new_unchecked(Vec::new());
+    let new_index = index.push(Default::default(), &idset, no_attributes);
+    let new_index = Arc::from(new_index);
+
+    let mut batch = WriteBatch::new();
+    let mut number_of_documents = inner.number_of_documents();
+    for id in idset.iter() {
+        for result in inner.get_document_fields(*id) {
+            let (_, attr, _) = result?;
+            batch.del_document_attribute(*id, attr);
+        }
+
+        // only the known documents are counted
+        if inner.external_id(*id)?.is_some() {
+            number_of_documents = number_of_documents.saturating_sub(1);
+        }
+        batch.del(external_id_key(*id));
+    }
+    batch.set(NUMBER_OF_DOCUMENTS_KEY, bincode::serialize(&number_of_documents)?);
+
+    let mut ranked_map = RankedMap::clone(&inner.ranked_map());
+    ranked_map.retain(|(id, _), _| idset.binary_search(id).is_err());
+    let ranked_map = Arc::new(ranked_map);
+
+    let mut facet_map = FacetMap::clone(&inner.facet_map());
+    facet_map.remove_documents(&idset);
+    let facet_map = Arc::new(facet_map);
+
+    inner.update(batch, new_index, ranked_map, facet_map)?;
+    inner.number_of_documents.store(Arc::new(number_of_documents));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::{Database, SchemaAttr};
+    use crate::database::ranked_key;
+    use crate::database::tests::simple_schema;
+
+    #[test]
+    fn deletion_purges_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+        let index = database.create_index("test".to_string(), simple_schema()).unwrap();
+
+        let mut addition = index.documents_addition();
+        addition.update_document(json!({ "id": 1, "title": "hello", "rank": 4 })).unwrap();
+        addition.update_document(json!({ "id": 2, "title": "hello", "rank": 5 })).unwrap();
+        addition.finalize().unwrap();
+
+        let id = index.document_id("1").unwrap().unwrap();
+        let mut deletion = index.documents_deletion();
+        deletion.delete_document(id);
+        deletion.finalize().unwrap();
+
+        let document: Option<serde_json::Value> = index.document(None, id).unwrap();
+        assert!(document.is_none());
+        assert!(index.0.get_document_fields(id).next().is_none());
+        assert!(index.external_id(id).unwrap().is_none());
+        assert!(index.ranked_map().keys().all(|(i, _)| *i != id));
+        assert!(index.0.inner.get(ranked_key(id, SchemaAttr(2))).unwrap().is_none());
+        assert_eq!(index.number_of_documents(), 1);
+
+        let result = index.query_builder().query("hello", 0..10).unwrap();
+        let ids: Vec<_> = result.hits.into_iter().map(|d| d.id).collect();
+        assert_eq!(ids, vec![index.document_id("2").unwrap().unwrap()]);
+    }
+}
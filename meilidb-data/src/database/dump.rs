@@ -0,0 +1,252 @@
+use std::io::{self, BufRead, Write};
+
+use serde::{Serialize, Deserialize};
+use meilidb_core::{DocumentId, PostingsEncoding};
+use serde_json::Value;
+
+use crate::Schema;
+use super::{Error, Database, Index, RawIndex, DocumentsAddition};
+use super::{external_id_key, extract_external_id_key};
+
+/// The version of the dump format, incremented on every incompatible change.
+const DUMP_VERSION: u32 = 1;
+
+/// The number of documents restored by each documents addition.
+const RESTORE_CHUNK_SIZE: usize = 1000;
+
+/// The first line of a dump, followed by one JSON document per line.
+#[derive(Serialize, Deserialize)]
+struct DumpHeader {
+    version: u32,
+    schema: Value,
+    // the first dumps did not write the encoding
+    #[serde(default)]
+    postings_encoding: PostingsEncoding,
+}
+
+/// The documents written in a dump.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DumpReport {
+    /// The number of documents written in the dump.
+    pub dumped_documents: usize,
+    /// The external ids of the documents without any stored attribute,
+    /// they can not be exported and are missing from the dump.
+    pub skipped_documents: Vec<String>,
+}
+
+/// Writes the schema and every stored document of the index as JSON lines,
+/// the updates are blocked during the dump to export a consistent view.
+pub fn dump_index<W: Write>(index: &Index, mut writer: W) -> Result<DumpReport, Error> {
+    let raw_index = &index.0;
+    let _lock = raw_index.update_lock.lock().unwrap();
+
+    let schema = raw_index.schema();
+
+    let mut schema_bytes = Vec::new();
+    schema.to_json(&mut schema_bytes).map_err(|e| Error::DumpError(e.to_string()))?;
+
+    let schema_json = serde_json::from_slice(&schema_bytes)?;
+    let header = DumpHeader {
+        version: DUMP_VERSION,
+        schema: schema_json,
+        postings_encoding: raw_index.postings_encoding(),
+    };
+    serde_json::to_writer(&mut writer, &header)?;
+    writer.write_all(b"\n")?;
+
+    let mut report = DumpReport::default();
+
+    // every document has an external id, even the ones without stored attributes
+    let start = external_id_key(DocumentId(u64::min_value()));
+    let end = external_id_key(DocumentId(u64::max_value()));
+    for result in raw_index.inner.range(start..=end) {
+        let (key, value) = result?;
+        let external_id = String::from_utf8_lossy(value.as_ref()).into_owned();
+        let document_id = match extract_external_id_key(&key) {
+            Some(document_id) => document_id,
+            None => return Err(Error::DumpError(format!("invalid external id key {:?}", key))),
+        };
+
+        let mut document: Value = match index.document(None, document_id)? {
+            Some(document) => document,
+            None => {
+                report.skipped_documents.push(external_id);
+                continue
+            },
+        };
+
+        // the identifier is not necessarily a stored attribute
+        if let (Some(identifier), Value::Object(map)) = (schema.identifier(), &mut document) {
+            if !map.contains_key(identifier) {
+                map.insert(identifier.to_owned(), Value::String(external_id));
+            }
+        }
+
+        serde_json::to_writer(&mut writer, &document)?;
+        writer.write_all(b"\n")?;
+        report.dumped_documents += 1;
+    }
+
+    writer.flush()?;
+
+    Ok(report)
+}
+
+/// Creates a new index from a dump, the documents are
+/// reindexed by successive documents additions.
+///
+/// The documents are added to an unlisted index that is moved under
+/// the given name on success and removed on failure, a crash in the
+/// middle of the restore leaves data that is removed on start.
+pub fn restore_index<R: BufRead>(
+    database: &Database,
+    name: String,
+    reader: R,
+) -> Result<Index, Error>
+{
+    let mut lines = reader.lines();
+
+    let header = match lines.next() {
+        Some(line) => line?,
+        None => return Err(Error::DumpError(String::from("the dump is empty"))),
+    };
+
+    let header: DumpHeader = serde_json::from_str(&header)?;
+    if header.version != DUMP_VERSION {
+        let message = format!("unsupported dump version {}", header.version);
+        return Err(Error::DumpError(message))
+    }
+
+    let schema_bytes = serde_json::to_vec(&header.schema)?;
+    let schema = Schema::from_json(schema_bytes.as_slice())
+        .map_err(|e| Error::DumpError(e.to_string()))?;
+
+    if database.indexes()?.contains(&name) {
+        return Err(Error::IndexAlreadyExists)
+    }
+
+    let (index_id, raw_index) = database.create_unlisted_index(schema)?;
+
+    let result = raw_index.set_postings_encoding(header.postings_encoding)
+        .and_then(|_| restore_documents(&raw_index, lines));
+
+    raw_index.close();
+    drop(raw_index);
+
+    match result {
+        Ok(()) => database.list_unlisted_index(&index_id, &name)?,
+        Err(e) => {
+            database.remove_unlisted_index(&index_id)?;
+            return Err(e)
+        },
+    }
+
+    match database.open_index(&name)? {
+        Some(index) => Ok(index),
+        None => Err(Error::DumpError(String::from("the restored index is not listed"))),
+    }
+}
+
+fn restore_documents<I>(raw_index: &RawIndex, lines: I) -> Result<(), Error>
+where I: Iterator<Item=io::Result<String>>,
+{
+    let mut addition = DocumentsAddition::from_raw(raw_index.clone());
+    let mut count = 0;
+
+    for line in lines {
+        let line = line?;
+        if line.trim().is_empty() { continue }
+
+        let document: Value = serde_json::from_str(&line)?;
+        addition.update_document(document)?;
+        count += 1;
+
+        if count == RESTORE_CHUNK_SIZE {
+            addition.finalize()?;
+            addition = DocumentsAddition::from_raw(raw_index.clone());
+            count = 0;
+        }
+    }
+
+    addition.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::database::extract_index_id;
+    use crate::database::tests::simple_schema;
+    use crate::schema::{SchemaBuilder, STORED, INDEXED};
+
+    #[test]
+    fn dump_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+
+        let index = database.create_index("test".to_string(), simple_schema()).unwrap();
+        index.set_postings_encoding(PostingsEncoding::Compressed).unwrap();
+
+        let mut addition = index.documents_addition();
+        addition.update_document(json!({ "id": "abc", "title": "hello", "rank": 1 })).unwrap();
+        addition.update_document(json!({ "id": "def", "title": "goodbye", "rank": 2 })).unwrap();
+        addition.finalize().unwrap();
+
+        let mut dump = Vec::new();
+        let report = index.dump(&mut dump).unwrap();
+        assert_eq!(report, DumpReport { dumped_documents: 2, skipped_documents: Vec::new() });
+
+        let restored = database.restore_index("restored".to_string(), dump.as_slice()).unwrap();
+        assert_eq!(restored.postings_encoding(), PostingsEncoding::Compressed);
+        assert_eq!(restored.number_of_documents(), 2);
+        assert_eq!(*restored.ranked_map(), *index.ranked_map());
+
+        let document: Option<serde_json::Value> = restored.document_by_key(None, "def").unwrap();
+        assert_eq!(document, Some(json!({ "id": "def", "title": "goodbye", "rank": 2 })));
+        assert_eq!(restored.query_builder().query("hello", 0..10).unwrap().hits.len(), 1);
+
+        match database.restore_index("test".to_string(), dump.as_slice()) {
+            Err(Error::IndexAlreadyExists) => (),
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+
+        // a failed restore does not leave any index behind
+        let mut broken = dump.clone();
+        broken.extend_from_slice(b"{ not json\n");
+        assert!(database.restore_index("broken".to_string(), broken.as_slice()).is_err());
+
+        let mut names = database.indexes().unwrap();
+        names.sort();
+        assert_eq!(names, vec!["restored".to_string(), "test".to_string()]);
+        let ids = database.indexes_ids().unwrap();
+        let tree_names = database.inner.tree_names();
+        let mut index_ids = tree_names.iter().filter_map(|n| extract_index_id(n));
+        assert!(index_ids.all(|id| ids.values().any(|i| *i == id)));
+    }
+
+    #[test]
+    fn dump_reports_skipped_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+
+        let mut builder = SchemaBuilder::with_identifier("id");
+        builder.new_attribute("id", INDEXED);
+        builder.new_attribute("title", STORED | INDEXED);
+        let index = database.create_index("test".to_string(), builder.build()).unwrap();
+
+        let mut addition = index.documents_addition();
+        addition.update_document(json!({ "id": "abc", "title": "hello" })).unwrap();
+        addition.update_document(json!({ "id": "def" })).unwrap();
+        addition.finalize().unwrap();
+
+        let mut dump = Vec::new();
+        let report = index.dump(&mut dump).unwrap();
+        assert_eq!(report.dumped_documents, 1);
+        assert_eq!(report.skipped_documents, vec!["def".to_string()]);
+
+        // the identifier is added back to the exported documents
+        let restored = database.restore_index("restored".to_string(), dump.as_slice()).unwrap();
+        assert_eq!(restored.number_of_documents(), 1);
+        assert!(restored.document_id("abc").unwrap().is_some());
+    }
+}
@@ -0,0 +1,234 @@
+use hashbrown::HashMap;
+use meilidb_core::{DocumentId, Index as PostingsIndex, PostingsEncoding, Segment};
+use meilidb_core::write_to_bytes::WriteToBytes;
+
+use crate::{Schema, SchemaAttr, RankedMap};
+use crate::serde::{compute_document_id, value_to_external_id};
+use super::{Error, WriteBatch, NUMBER_OF_DOCUMENTS_KEY};
+use super::{segment_key, document_key, extract_document_key, external_id_key};
+
+/// The key under which the previous versions stored the whole word index.
+const LEGACY_WORD_INDEX_KEY: &str = "word-index";
+
+/// The key under which the previous versions stored the whole ranked map.
+const LEGACY_RANKED_MAP_KEY: &str = "ranked-map";
+
+/// Moves the word index and the ranked map of an index written by the previous versions
+/// into the current layout, the word index becomes the first segment of the index.
+///
+/// The previous versions did not keep the external ids and computed the internal ids
+/// with an unstable hash, the documents are moved under the ids computed from their
+/// stored identifier. Everything is written in a single batch along with the deletion
+/// of the old keys, the migration is done only once.
+pub fn migrate_legacy_index(tree: &sled::Tree) -> Result<(), Error> {
+    let bytes = match tree.get(LEGACY_WORD_INDEX_KEY)? {
+        Some(bytes) => bytes,
+        None => return Ok(()),
+    };
+
+    let index = match PostingsIndex::from_legacy_bytes(bytes.as_ref()) {
+        Ok(index) => index,
+        Err(e) => return Err(Error::CorruptedSegment { segment: 0, message: e.to_string() }),
+    };
+
+    let schema = match tree.get("schema")? {
+        Some(bytes) => Schema::read_from_bin(bytes.as_ref())?,
+        None => return Err(Error::SchemaMissing),
+    };
+
+    let identifier = schema.identifier_name().to_owned();
+    let identifier_attr = match schema.attribute(&identifier) {
+        Some(attr) if schema.props(attr).is_stored() => attr,
+        _ => return Err(Error::IdentifierNotStored(identifier)),
+    };
+
+    let mut batch = WriteBatch::new();
+    let mut fields = Vec::new();
+    let mut new_ids = HashMap::new();
+    let mut external_ids: HashMap<DocumentId, String> = HashMap::new();
+
+    // the old keys are deleted before the new ones are written, they can be the same
+    let start = document_key(DocumentId(u64::min_value()), SchemaAttr::min());
+    let end = document_key(DocumentId(u64::max_value()), SchemaAttr::max());
+    for result in tree.range(start..=end) {
+        let (key, value) = result?;
+        let (id, attr) = extract_document_key(&key)?;
+
+        if attr == identifier_attr {
+            let value: serde_json::Value = rmp_serde::from_slice(value.as_ref())?;
+            let external_id = value_to_external_id(&value)?;
+            let new_id = compute_document_id(&external_id);
+
+            if let Some(known_id) = external_ids.get(&new_id) {
+                return Err(Error::DocumentIdCollision(known_id.clone(), external_id))
+            }
+
+            external_ids.insert(new_id, external_id);
+            new_ids.insert(id, new_id);
+        }
+
+        batch.del(key.to_vec());
+        fields.push((id, attr, value));
+    }
+
+    for (id, attr, value) in fields {
+        match new_ids.get(&id) {
+            Some(new_id) => batch.set_document_attribute(*new_id, attr, value.to_vec()),
+            None => return Err(Error::IdentifierNotStored(identifier)),
+        }
+    }
+
+    for (id, external_id) in &external_ids {
+        batch.set(external_id_key(*id), external_id.as_str());
+    }
+
+    let number_of_documents = external_ids.len() as u64;
+    batch.set(NUMBER_OF_DOCUMENTS_KEY, bincode::serialize(&number_of_documents)?);
+
+    // the postings of the documents without stored fields keep their id
+    let new_id = |id| new_ids.get(&id).cloned().unwrap_or(id);
+    let index = index.map_documents(new_id);
+
+    let mut segment = Segment::empty(0);
+    batch.set_segment_postings(segment.id, &index, PostingsEncoding::default());
+    segment.words = index.map;
+    batch.set(segment_key(segment.id), segment.into_bytes());
+    batch.del(LEGACY_WORD_INDEX_KEY);
+
+    if let Some(bytes) = tree.get(LEGACY_RANKED_MAP_KEY)? {
+        let ranked_map: RankedMap = bincode::deserialize(bytes.as_ref())?;
+        let ranked_map: RankedMap = ranked_map.into_iter()
+            .map(|((id, attr), number)| ((new_id(id), attr), number))
+            .collect();
+
+        batch.set_ranked_map_changes(&RankedMap::default(), &ranked_map)?;
+        batch.del(LEGACY_RANKED_MAP_KEY);
+    }
+
+    batch.apply(tree)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use meilidb_core::{DocIndex, IndexBuilder};
+    use sdset::Set;
+    use serde_json::json;
+    use crate::{Database, Number};
+    use crate::schema::{SchemaBuilder, STORED, INDEXED, RANKED};
+    use crate::database::{INDEX_IDS_KEY, index_name, document_key};
+    use crate::database::tests::{simple_schema, reopen_index};
+
+    /// Writes a word index in the layout of the previous versions,
+    /// their document indexes were 16 bytes long.
+    fn legacy_word_index(words: &[(&str, Vec<DocIndex>)]) -> Vec<u8> {
+        let mut builder = IndexBuilder::new();
+        for (word, indexes) in words {
+            builder.insert(word, Set::new_unchecked(indexes)).unwrap();
+        }
+        let fst = builder.build().map.as_fst().as_bytes().to_vec();
+
+        let mut ranges = Vec::new();
+        let mut indexes = Vec::new();
+        for (_, postings) in words {
+            ranges.write_u64::<LittleEndian>(indexes.len() as u64 / 16).unwrap();
+            for x in postings {
+                indexes.write_u64::<LittleEndian>(x.document_id.0).unwrap();
+                indexes.write_u16::<LittleEndian>(x.attribute).unwrap();
+                indexes.write_u16::<LittleEndian>(x.word_index as u16).unwrap();
+                indexes.write_u16::<LittleEndian>(x.char_index as u16).unwrap();
+                indexes.write_u16::<LittleEndian>(x.char_length).unwrap();
+            }
+            ranges.write_u64::<LittleEndian>(indexes.len() as u64 / 16).unwrap();
+        }
+
+        let mut bytes = Vec::new();
+        for part in &[fst, ranges, indexes] {
+            bytes.write_u64::<LittleEndian>(part.len() as u64).unwrap();
+            bytes.extend_from_slice(part);
+        }
+        bytes
+    }
+
+    /// Writes an index as the previous versions did, the indexes were not listed.
+    fn write_legacy_index(database: &Database, schema: Schema, id: DocumentId) -> Arc<sled::Tree> {
+        let doc_index = |word_index, char_index, char_length| {
+            DocIndex { document_id: id, attribute: 1, word_index, char_index, char_length }
+        };
+
+        let tree = database.inner.open_tree(index_name("test")).unwrap();
+        let mut schema_bytes = Vec::new();
+        schema.write_to_bin(&mut schema_bytes).unwrap();
+        tree.insert("schema", schema_bytes).unwrap();
+
+        let words = vec![("hello", vec![doc_index(0, 0, 5)]), ("world", vec![doc_index(1, 6, 5)])];
+        tree.insert(LEGACY_WORD_INDEX_KEY, legacy_word_index(&words)).unwrap();
+
+        let mut ranked_map = RankedMap::default();
+        ranked_map.insert((id, SchemaAttr(2)), Number::Unsigned(4));
+        tree.insert(LEGACY_RANKED_MAP_KEY, bincode::serialize(&ranked_map).unwrap()).unwrap();
+
+        let fields = [json!(1), json!("hello world"), json!(4)];
+        for (attr, value) in fields.iter().enumerate() {
+            let key = document_key(id, SchemaAttr(attr as u16));
+            tree.insert(key, rmp_serde::to_vec_named(value).unwrap()).unwrap();
+        }
+
+        database.metadata.remove(INDEX_IDS_KEY).unwrap();
+        tree
+    }
+
+    #[test]
+    fn open_legacy_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+        let tree = write_legacy_index(&database, simple_schema(), DocumentId(7));
+
+        let index = database.open_index("test").unwrap().unwrap();
+        assert!(tree.get(LEGACY_WORD_INDEX_KEY).unwrap().is_none());
+        assert!(tree.get(LEGACY_RANKED_MAP_KEY).unwrap().is_none());
+
+        // the document is moved under the id computed from its identifier
+        let id = compute_document_id("1");
+        assert_eq!(index.document_id("1").unwrap(), Some(id));
+        assert_eq!(index.number_of_documents(), 1);
+        assert_eq!(index.ranked_map().get(&(id, SchemaAttr(2))), Some(&Number::Unsigned(4)));
+
+        let result = index.query_builder().query("world", 0..10).unwrap();
+        let hits: Vec<_> = result.hits.iter().map(|d| d.id).collect();
+        assert_eq!(hits, vec![id]);
+
+        let document: serde_json::Value = index.document_by_key(None, "1").unwrap().unwrap();
+        assert_eq!(document, json!({ "id": 1, "title": "hello world", "rank": 4 }));
+        assert!(index.document::<serde_json::Value>(None, DocumentId(7)).unwrap().is_none());
+
+        // the migrated word index is read from its segment
+        let index = reopen_index(&database, "test");
+        assert_eq!(index.word_index().segments().len(), 1);
+        assert_eq!(index.query_builder().query("hello", 0..10).unwrap().hits.len(), 1);
+    }
+
+    #[test]
+    fn legacy_identifier_not_stored() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+
+        let mut builder = SchemaBuilder::with_identifier("id");
+        builder.new_attribute("id", INDEXED);
+        builder.new_attribute("title", STORED | INDEXED);
+        builder.new_attribute("rank", STORED | RANKED);
+        let tree = write_legacy_index(&database, builder.build(), DocumentId(7));
+
+        match database.open_index("test") {
+            Err(Error::IdentifierNotStored(ref name)) if name == "id" => (),
+            result => panic!("unexpected result {:?}", result.map(drop)),
+        }
+
+        // nothing is migrated
+        assert!(tree.get(LEGACY_WORD_INDEX_KEY).unwrap().is_some());
+    }
+}
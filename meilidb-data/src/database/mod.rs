@@ -0,0 +1,2130 @@
+mod documents_addition;
+mod documents_deletion;
+mod dump;
+mod migration;
+mod reindex;
+mod schema_update;
+mod stats;
+mod update;
+mod verify;
+mod word_index_store;
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Cursor, BufRead, Write};
+use std::iter::FromIterator;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::SystemTime;
+use std::{error, fmt};
+
+use arc_swap::{ArcSwap, Lease};
+use byteorder::{ReadBytesExt, BigEndian};
+use hashbrown::HashMap;
+use log::error;
+use meilidb_core::criterion::Criteria;
+use meilidb_core::QueryBuilder;
+use meilidb_core::shared_data_cursor::{FromSharedDataCursor, SharedDataCursor};
+use meilidb_core::write_to_bytes::WriteToBytes;
+use meilidb_core::{DocumentId, Index as PostingsIndex, PostingsEncoding};
+use meilidb_core::{Segment, SegmentedIndex as WordIndex, merge_segments};
+use meilidb_core::{write_postings, mmap_words};
+use rmp_serde::decode::{Error as RmpError};
+use serde::de;
+use sled::IVec;
+
+use crate::{Schema, SchemaAttr, RankedMap, FacetMap};
+use crate::serde::compute_document_id;
+use crate::serde::{Deserializer, SerializerError};
+
+use self::dump::{dump_index, restore_index};
+use self::migration::migrate_legacy_index;
+use self::schema_update::apply_schema_update;
+use self::update::{Update, Updates, spawn_update_system};
+use self::reindex::reindex_index;
+use self::stats::index_stats;
+use self::verify::verify_index;
+pub use self::documents_addition::DocumentsAddition;
+pub use self::documents_deletion::DocumentsDeletion;
+pub use self::dump::DumpReport;
+pub use self::reindex::ReindexProgress;
+pub use self::stats::IndexStats;
+pub use self::update::{UpdateEvent, UpdateStatus, UpdateResult, UpdateType};
+pub use self::verify::IntegrityReport;
+pub use self::word_index_store::{WordIndexStore, DocumentsFilter, QueryBuilderExt};
+
+#[derive(Debug)]
+pub enum Error {
+    SchemaDiffer,
+    SchemaMissing,
+    IndexAlreadyExists,
+    MissingDocumentId { identifier: String, document: String },
+    InvalidDocumentId { identifier: String, type_name: &'static str, document: String },
+    IdentifierNotInferred { document: String },
+    DocumentIdCollision(String, String),
+    AttributeNotStored(String),
+    IdentifierNotStored(String),
+    AttributeNotFaceted(String),
+    DumpError(String),
+    CorruptedSegment { segment: u64, message: String },
+    CorruptedPostings { segment: u64, word: String, message: String },
+    CorruptedDocumentKey(Vec<u8>),
+    IoError(io::Error),
+    SledError(sled::Error),
+    BincodeError(bincode::Error),
+    RmpDecodeError(RmpError),
+    SerdeJsonError(serde_json::Error),
+    SerializerError(SerializerError),
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::IoError(error)
+    }
+}
+
+impl From<sled::Error> for Error {
+    fn from(error: sled::Error) -> Error {
+        Error::SledError(error)
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(error: bincode::Error) -> Error {
+        Error::BincodeError(error)
+    }
+}
+
+impl From<RmpError> for Error {
+    fn from(error: RmpError) -> Error {
+        Error::RmpDecodeError(error)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Error {
+        Error::SerdeJsonError(error)
+    }
+}
+
+impl From<SerializerError> for Error {
+    fn from(error: SerializerError) -> Error {
+        Error::SerializerError(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Error::*;
+        match self {
+            SchemaDiffer => write!(f, "schemas differ"),
+            SchemaMissing => write!(f, "this index does not have a schema"),
+            IndexAlreadyExists => write!(f, "an index with this name already exists"),
+            MissingDocumentId { identifier, document } => {
+                write!(f, "the {} document id is missing from the document {}",
+                    identifier, document)
+            },
+            InvalidDocumentId { identifier, type_name, document } => {
+                write!(f, "the {} document id of the document {} is a {}, \
+                           only strings and integers are valid ids",
+                    identifier, document, type_name)
+            },
+            IdentifierNotInferred { document } => {
+                write!(f, "the document id can not be inferred from the document {}, \
+                           it must contain an \"id\" field or a field ending with \"_id\"",
+                    document)
+            },
+            DocumentIdCollision(a, b) => {
+                write!(f, "the {} and {} document ids collide, use another id", a, b)
+            },
+            AttributeNotStored(name) => {
+                write!(f, "the {} attribute values are not stored, they can not be retrieved", name)
+            },
+            IdentifierNotStored(name) => {
+                write!(f, "the {} identifier values are not stored, the documents \
+                    written by a previous version can not be migrated", name)
+            },
+            AttributeNotFaceted(name) => {
+                write!(f, "the {} attribute is not faceted, its values can not be counted", name)
+            },
+            DumpError(message) => write!(f, "dump error; {}", message),
+            CorruptedSegment { segment, message } => {
+                write!(f, "the segment {} of the word index is corrupted; {}", segment, message)
+            },
+            CorruptedPostings { segment, word, message } => {
+                write!(f, "the postings of the word {:?} in the segment {} are corrupted; {}",
+                    word, segment, message)
+            },
+            CorruptedDocumentKey(key) => write!(f, "the document key {:?} is corrupted", key),
+            IoError(e) => write!(f, "io error; {}", e),
+            SledError(e) => write!(f, "sled error; {}", e),
+            BincodeError(e) => write!(f, "bincode error; {}", e),
+            RmpDecodeError(e) => write!(f, "rmp decode error; {}", e),
+            SerdeJsonError(e) => write!(f, "serde json error; {}", e),
+            SerializerError(e) => write!(f, "serializer error; {}", e),
+        }
+    }
+}
+
+impl error::Error for Error { }
+
+const POSTINGS_ENCODING_KEY: &str = "word-postings-encoding";
+const LAST_UPDATE_KEY: &str = "last-update";
+const NUMBER_OF_DOCUMENTS_KEY: &str = "number-of-documents";
+const INDEXES_KEY: &str = "indexes";
+const INDEX_IDS_KEY: &str = "index-ids";
+const GARBAGE_SEGMENTS_KEY: &str = "garbage-segments";
+
+fn index_name(id: &str) -> Vec<u8> {
+    format!("index-{}", id).into_bytes()
+}
+
+fn updates_name(id: &str) -> Vec<u8> {
+    format!("updates-{}", id).into_bytes()
+}
+
+fn words_dir(database_path: &Path, id: &str) -> PathBuf {
+    database_path.join("word-index").join(id)
+}
+
+fn segment_words_path(words_dir: &Path, id: u64) -> PathBuf {
+    words_dir.join(format!("segment-{}", id))
+}
+
+fn extract_segment_words_id(path: &Path) -> Option<u64> {
+    let name = path.file_name()?.to_str()?;
+    name.get("segment-".len()..)?.parse().ok()
+}
+
+/// Writes the words of a segment in a file, the file is only
+/// visible under its final path once entirely written on disk.
+fn write_segment_words(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let tmp_path = path.with_extension("tmp");
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+
+    fs::rename(tmp_path, path)
+}
+
+fn copy_dir_files(from: &Path, to: &Path) -> io::Result<()> {
+    if !from.exists() { return Ok(()) }
+
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let path = entry?.path();
+        if let Some(name) = path.file_name() {
+            fs::copy(&path, to.join(name))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn extract_index_id(tree_name: &[u8]) -> Option<String> {
+    if tree_name.starts_with(b"index-") {
+        let id = &tree_name[b"index-".len()..];
+        String::from_utf8(id.to_vec()).ok()
+    } else {
+        None
+    }
+}
+
+fn extract_updates_id(tree_name: &[u8]) -> Option<String> {
+    if tree_name.starts_with(b"updates-") {
+        let id = &tree_name[b"updates-".len()..];
+        String::from_utf8(id.to_vec()).ok()
+    } else {
+        None
+    }
+}
+
+fn copy_tree(source: &sled::Tree, destination: &sled::Tree) -> sled::Result<()> {
+    for result in source.iter() {
+        let (key, value) = result?;
+        destination.insert(key, value)?;
+    }
+    Ok(())
+}
+
+fn segment_key(id: u64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"segment-");
+    bytes.extend_from_slice(&id.to_be_bytes()[..]);
+    bytes
+}
+
+fn extract_segment_id(key: &[u8]) -> Option<u64> {
+    let bytes = key.get(b"segment-".len()..)?;
+    if bytes.len() != 8 { return None }
+
+    let mut array = [0; 8];
+    array.copy_from_slice(bytes);
+    Some(u64::from_be_bytes(array))
+}
+
+fn postings_prefix(segment: u64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"postings-");
+    bytes.extend_from_slice(&segment.to_be_bytes()[..]);
+    bytes
+}
+
+fn postings_key(segment: u64, word: &[u8]) -> Vec<u8> {
+    let mut bytes = postings_prefix(segment);
+    bytes.extend_from_slice(word);
+    bytes
+}
+
+fn document_prefix(id: DocumentId) -> Vec<u8> {
+    let DocumentId(document_id) = id;
+
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"document-");
+    bytes.extend_from_slice(&document_id.to_be_bytes()[..]);
+    bytes
+}
+
+fn document_key(id: DocumentId, attr: SchemaAttr) -> Vec<u8> {
+    let SchemaAttr(schema_attr) = attr;
+
+    let mut bytes = document_prefix(id);
+    bytes.extend_from_slice(&schema_attr.to_be_bytes()[..]);
+    bytes
+}
+
+fn external_id_key(id: DocumentId) -> Vec<u8> {
+    let DocumentId(document_id) = id;
+
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"external-id-");
+    bytes.extend_from_slice(&document_id.to_be_bytes()[..]);
+    bytes
+}
+
+fn extract_external_id_key(key: &[u8]) -> Option<DocumentId> {
+    let bytes = key.get(b"external-id-".len()..)?;
+    if bytes.len() != 8 { return None }
+
+    let mut array = [0; 8];
+    array.copy_from_slice(bytes);
+    Some(DocumentId(u64::from_be_bytes(array)))
+}
+
+fn ranked_key(id: DocumentId, attr: SchemaAttr) -> Vec<u8> {
+    let DocumentId(document_id) = id;
+    let SchemaAttr(schema_attr) = attr;
+
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"ranked-value-");
+    bytes.extend_from_slice(&document_id.to_be_bytes()[..]);
+    bytes.extend_from_slice(&schema_attr.to_be_bytes()[..]);
+    bytes
+}
+
+fn extract_ranked_key(key: &[u8]) -> Option<(DocumentId, SchemaAttr)> {
+    let bytes = key.get(b"ranked-value-".len()..)?;
+    if bytes.len() != 10 { return None }
+
+    let mut key = Cursor::new(bytes);
+    let document_id = key.read_u64::<BigEndian>().map(DocumentId).ok()?;
+    let schema_attr = key.read_u16::<BigEndian>().map(SchemaAttr).ok()?;
+
+    Some((document_id, schema_attr))
+}
+
+const FACET_VALUE_PREFIX: &[u8] = b"facet-value-";
+
+/// The values of an attribute are sorted and the ids of the documents
+/// having one of them follow each other, like in the facet map.
+fn facet_key(attr: SchemaAttr, value: &str, id: DocumentId) -> Vec<u8> {
+    let SchemaAttr(schema_attr) = attr;
+    let DocumentId(document_id) = id;
+
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(FACET_VALUE_PREFIX);
+    bytes.extend_from_slice(&schema_attr.to_be_bytes()[..]);
+    bytes.extend_from_slice(value.as_bytes());
+    bytes.extend_from_slice(&document_id.to_be_bytes()[..]);
+    bytes
+}
+
+fn extract_facet_key(key: &[u8]) -> Option<(SchemaAttr, String, DocumentId)> {
+    let bytes = key.get(FACET_VALUE_PREFIX.len()..)?;
+    if bytes.len() < 10 { return None }
+
+    let (attr, bytes) = bytes.split_at(2);
+    let (value, id) = bytes.split_at(bytes.len() - 8);
+
+    let schema_attr = Cursor::new(attr).read_u16::<BigEndian>().map(SchemaAttr).ok()?;
+    let value = String::from_utf8(value.to_vec()).ok()?;
+    let document_id = Cursor::new(id).read_u64::<BigEndian>().map(DocumentId).ok()?;
+
+    Some((schema_attr, value, document_id))
+}
+
+fn read_ranked_map(tree: &sled::Tree) -> Result<RankedMap, Error> {
+    let start = ranked_key(DocumentId(u64::min_value()), SchemaAttr::min());
+    let end = ranked_key(DocumentId(u64::max_value()), SchemaAttr::max());
+
+    let mut ranked_map = RankedMap::default();
+    for result in tree.range(start..=end) {
+        let (key, bytes) = result?;
+        if let Some(key) = extract_ranked_key(&key) {
+            ranked_map.insert(key, bincode::deserialize(bytes.as_ref())?);
+        }
+    }
+
+    Ok(ranked_map)
+}
+
+fn read_facet_map(tree: &sled::Tree) -> Result<FacetMap, Error> {
+    let mut facet_map = FacetMap::default();
+    for result in tree.range(FACET_VALUE_PREFIX.to_vec()..) {
+        let (key, _) = result?;
+        if !key.starts_with(FACET_VALUE_PREFIX) { break }
+
+        if let Some((attr, value, id)) = extract_facet_key(&key) {
+            facet_map.insert(id, attr, value);
+        }
+    }
+
+    Ok(facet_map)
+}
+
+trait CursorExt {
+    fn consume_if_eq(&mut self, needle: &[u8]) -> bool;
+}
+
+impl<T: AsRef<[u8]>> CursorExt for Cursor<T> {
+    fn consume_if_eq(&mut self, needle: &[u8]) -> bool {
+        let position = self.position() as usize;
+        let slice = self.get_ref().as_ref();
+
+        if slice[position..].starts_with(needle) {
+            self.consume(needle.len());
+            true
+        } else {
+            false
+        }
+    }
+}
+
+fn extract_document_key(key: &[u8]) -> io::Result<(DocumentId, SchemaAttr)> {
+    let mut key = Cursor::new(key);
+
+    if !key.consume_if_eq(b"document-") {
+        return Err(io::Error::from(io::ErrorKind::InvalidData))
+    }
+
+    let document_id = key.read_u64::<BigEndian>().map(DocumentId)?;
+    let schema_attr = key.read_u16::<BigEndian>().map(SchemaAttr)?;
+
+    Ok((document_id, schema_attr))
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct DatabaseOptions {
+    /// The words of the new segments of the word indexes are written in files next
+    /// to the database, the files are memory mapped instead of being read in memory.
+    pub mmap_word_index: bool,
+}
+
+#[derive(Clone)]
+pub struct Database {
+    opened: Arc<ArcSwap<HashMap<String, RawIndex>>>,
+    opening_lock: Arc<Mutex<()>>,
+    path: PathBuf,
+    options: DatabaseOptions,
+    inner: sled::Db,
+    // the map of the indexes names to the ids naming their data is the
+    // commit point of the creation, deletion and renaming of the indexes
+    metadata: Arc<sled::Tree>,
+}
+
+impl Database {
+    pub fn start_default<P: AsRef<Path>>(path: P) -> Result<Database, Error> {
+        Database::start_with_options(path, DatabaseOptions::default())
+    }
+
+    pub fn start_with_options<P: AsRef<Path>>(
+        path: P,
+        options: DatabaseOptions,
+    ) -> Result<Database, Error>
+    {
+        let path = path.as_ref().to_path_buf();
+        let inner = sled::Db::open(&path)?;
+        let metadata = inner.open_tree("metadata")?;
+        let opened = Arc::new(ArcSwap::new(Arc::new(HashMap::new())));
+        let opening_lock = Arc::new(Mutex::new(()));
+
+        let database = Database { opened, opening_lock, path, options, inner, metadata };
+        database.remove_unlisted_indexes()?;
+
+        Ok(database)
+    }
+
+    /// Opens an index, fails with `Error::CorruptedSegment` if a segment
+    /// of its word index can not be read, it must be repaired first.
+    pub fn open_index(&self, name: &str) -> Result<Option<Index>, Error> {
+        self.open_index_with_repair(name, false)
+    }
+
+    /// Opens an index where the segments that can not be read are replaced by
+    /// empty ones and rebuilds its word index from the stored documents.
+    pub fn repair_index<F>(&self, name: &str, progress: F) -> Result<Option<Index>, Error>
+    where F: FnMut(ReindexProgress),
+    {
+        match self.open_index_with_repair(name, true)? {
+            Some(index) => {
+                index.reindex(progress)?;
+                Ok(Some(index))
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn open_index_with_repair(&self, name: &str, repair: bool) -> Result<Option<Index>, Error> {
+        // check if the index was already opened
+        if let Some(raw_index) = self.opened.lease().get(name) {
+            return Ok(Some(Index(raw_index.clone())))
+        }
+
+        // an index must be opened only once, it owns an update writer thread
+        let _lock = self.opening_lock.lock().unwrap();
+        if let Some(raw_index) = self.opened.lease().get(name) {
+            return Ok(Some(Index(raw_index.clone())))
+        }
+
+        if let Some(id) = self.indexes_ids()?.get(name) {
+            let tree = self.inner.open_tree(index_name(id))?;
+            let updates = self.inner.open_tree(updates_name(id))?;
+            let words_dir = words_dir(&self.path, id);
+            let mmap_words = self.options.mmap_word_index;
+            let raw_index = RawIndex::from_raw(tree, updates, words_dir, mmap_words, repair)?;
+
+            self.opened.rcu(|opened| {
+                let mut opened = HashMap::clone(opened);
+                opened.insert(name.to_string(), raw_index.clone());
+                opened
+            });
+
+            return Ok(Some(Index(raw_index)))
+        }
+
+        Ok(None)
+    }
+
+    pub fn create_index(&self, name: String, schema: Schema) -> Result<Index, Error> {
+        match self.open_index(&name)? {
+            Some(index) => {
+                // the identifier inferred by the first documents is part of the stored schema
+                let current = index.schema();
+                let schema = match (schema.identifier(), current.identifier()) {
+                    (None, Some(inferred)) => schema.with_identifier(inferred),
+                    _ => schema,
+                };
+
+                if *current != schema {
+                    return Err(Error::SchemaDiffer);
+                }
+
+                Ok(index)
+            },
+            None => {
+                let _lock = self.opening_lock.lock().unwrap();
+                if let Some(raw_index) = self.opened.lease().get(&name) {
+                    return Ok(Index(raw_index.clone()))
+                }
+
+                let id = self.new_index_id()?;
+                let tree = self.inner.open_tree(index_name(&id))?;
+                let updates = self.inner.open_tree(updates_name(&id))?;
+                let words_dir = words_dir(&self.path, &id);
+                let mmap = self.options.mmap_word_index;
+                let raw_index = RawIndex::new_from_raw(tree, updates, words_dir, mmap, schema)?;
+
+                let mut ids = self.indexes_ids()?;
+                ids.insert(name.clone(), id);
+                self.set_indexes_ids(&ids)?;
+
+                self.opened.rcu(|opened| {
+                    let mut opened = HashMap::clone(opened);
+                    opened.insert(name.clone(), raw_index.clone());
+                    opened
+                });
+
+                Ok(Index(raw_index))
+            },
+        }
+    }
+
+    /// Creates an index from a dump made with `Index::dump`,
+    /// fails if an index with the same name already exists.
+    ///
+    /// The documents are restored in a temporary index that only
+    /// takes the name once all of them are successfully restored.
+    pub fn restore_index<R: BufRead>(&self, name: String, reader: R) -> Result<Index, Error> {
+        restore_index(self, name, reader)
+    }
+
+    /// Returns the names of the indexes in order.
+    pub fn indexes(&self) -> Result<Vec<String>, Error> {
+        Ok(self.indexes_ids()?.into_iter().map(|(name, _)| name).collect())
+    }
+
+    /// Removes the index and all of its data, returns `false` if it did not exist.
+    ///
+    /// The index handles that are still alive must not be used anymore.
+    pub fn delete_index(&self, name: &str) -> Result<bool, Error> {
+        let _lock = self.opening_lock.lock().unwrap();
+
+        if let Some(raw_index) = self.remove_opened(name) {
+            raw_index.close();
+        }
+
+        let mut ids = self.indexes_ids()?;
+        let id = match ids.remove(name) {
+            Some(id) => id,
+            None => return Ok(false),
+        };
+
+        // once unlisted the data of the index is removed
+        // on start if a crash happens in between
+        self.set_indexes_ids(&ids)?;
+        self.remove_index_data(&id)?;
+
+        Ok(true)
+    }
+
+    /// Lists an index under a new name, returns `false` if it did not exist.
+    ///
+    /// The data of the index is named by its id and stays where it is, only the
+    /// map of the indexes names is written. The index handles that are still
+    /// alive can be used, they are the handles of the index under its new name.
+    pub fn rename_index(&self, old_name: &str, new_name: &str) -> Result<bool, Error> {
+        let _lock = self.opening_lock.lock().unwrap();
+
+        let mut ids = self.indexes_ids()?;
+        if !ids.contains_key(old_name) {
+            return Ok(false)
+        }
+
+        if ids.contains_key(new_name) {
+            return Err(Error::IndexAlreadyExists)
+        }
+
+        let id = ids.remove(old_name).unwrap();
+        ids.insert(new_name.to_string(), id);
+        self.set_indexes_ids(&ids)?;
+
+        self.opened.rcu(|opened| {
+            let mut opened = HashMap::clone(opened);
+            if let Some(raw_index) = opened.remove(old_name) {
+                opened.insert(new_name.to_string(), raw_index);
+            }
+            opened
+        });
+
+        Ok(true)
+    }
+
+    /// Creates an index that is not listed nor opened and returns it along with its id,
+    /// its data is removed on start if it is not listed with `list_unlisted_index` before,
+    /// it must be closed by the caller.
+    fn create_unlisted_index(&self, schema: Schema) -> Result<(String, RawIndex), Error> {
+        let _lock = self.opening_lock.lock().unwrap();
+
+        let id = self.new_index_id()?;
+        let tree = self.inner.open_tree(index_name(&id))?;
+        let updates = self.inner.open_tree(updates_name(&id))?;
+        let words_dir = words_dir(&self.path, &id);
+        let mmap = self.options.mmap_word_index;
+
+        let raw_index = RawIndex::new_from_raw(tree, updates, words_dir, mmap, schema)?;
+
+        Ok((id, raw_index))
+    }
+
+    /// Lists an index created with `create_unlisted_index` under the given name,
+    /// the unlisted data is removed if an index with this name already exists.
+    fn list_unlisted_index(&self, id: &str, name: &str) -> Result<(), Error> {
+        let _lock = self.opening_lock.lock().unwrap();
+
+        let mut ids = self.indexes_ids()?;
+        if ids.contains_key(name) {
+            self.remove_index_data(id)?;
+            return Err(Error::IndexAlreadyExists)
+        }
+
+        ids.insert(name.to_string(), id.to_string());
+        self.set_indexes_ids(&ids)
+    }
+
+    /// Removes the data of an index created with `create_unlisted_index`.
+    fn remove_unlisted_index(&self, id: &str) -> Result<(), Error> {
+        let _lock = self.opening_lock.lock().unwrap();
+        self.remove_index_data(id)
+    }
+
+    /// Copies every index, with its pending updates, into a new database at the given path,
+    /// the copy is consistent as the updates of all the indexes are blocked during the copy.
+    ///
+    /// The snapshot is a database that can be opened with `Database::start_default`,
+    /// the updates being processed at the time of the snapshot will be processed again.
+    /// Its files are released in background, the same process can not open it right away.
+    pub fn snapshot<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        if path.exists() {
+            let message = "the snapshot path already exists";
+            return Err(Error::IoError(io::Error::new(io::ErrorKind::AlreadyExists, message)))
+        }
+
+        // indexes can not be opened, created or removed during the snapshot
+        // and the ones that are opened are not updated anymore
+        let _lock = self.opening_lock.lock().unwrap();
+        let opened = self.opened.load();
+
+        let mut update_locks = Vec::with_capacity(opened.len());
+        let mut frozen_updates = Vec::with_capacity(opened.len());
+        for raw_index in opened.values() {
+            update_locks.push(raw_index.update_lock.lock().unwrap());
+            frozen_updates.push(raw_index.updates.freeze());
+        }
+
+        let snapshot = sled::Db::open(path)?;
+        let ids = self.indexes_ids()?;
+
+        let snapshot_metadata = snapshot.open_tree("metadata")?;
+        snapshot_metadata.insert(INDEX_IDS_KEY, bincode::serialize(&ids)?)?;
+
+        for id in ids.values() {
+            let tree = self.inner.open_tree(index_name(id))?;
+            let snapshot_tree = snapshot.open_tree(index_name(id))?;
+            copy_tree(&tree, &snapshot_tree)?;
+
+            let updates = self.inner.open_tree(updates_name(id))?;
+            let snapshot_updates = snapshot.open_tree(updates_name(id))?;
+            copy_tree(&updates, &snapshot_updates)?;
+
+            copy_dir_files(&words_dir(&self.path, id), &words_dir(path, id))?;
+        }
+
+        snapshot.flush()?;
+
+        Ok(())
+    }
+
+    /// Returns the names of the indexes mapped to the ids naming their trees and words
+    /// directory. The indexes listed before they had ids are named by their name and
+    /// the databases created before the indexes were listed get a list of their trees.
+    fn indexes_ids(&self) -> Result<BTreeMap<String, String>, Error> {
+        if let Some(bytes) = self.metadata.get(INDEX_IDS_KEY)? {
+            return Ok(bincode::deserialize(bytes.as_ref())?)
+        }
+
+        let names: Vec<String> = match self.metadata.get(INDEXES_KEY)? {
+            Some(bytes) => bincode::deserialize(bytes.as_ref())?,
+            None => {
+                self.inner.tree_names()
+                    .into_iter()
+                    .filter_map(|tn| extract_index_id(&tn))
+                    .collect()
+            },
+        };
+
+        let ids = names.into_iter().map(|name| (name.clone(), name)).collect();
+        self.set_indexes_ids(&ids)?;
+        self.metadata.remove(INDEXES_KEY)?;
+
+        Ok(ids)
+    }
+
+    fn set_indexes_ids(&self, ids: &BTreeMap<String, String>) -> Result<(), Error> {
+        self.metadata.insert(INDEX_IDS_KEY, bincode::serialize(ids)?)?;
+        Ok(())
+    }
+
+    /// Generates the id of a new index, the ids of the indexes listed
+    /// before they had ids are their names and are never reused.
+    fn new_index_id(&self) -> Result<String, Error> {
+        let ids = self.indexes_ids()?;
+        loop {
+            let id = self.inner.generate_id()?.to_string();
+            if ids.values().all(|i| *i != id) { return Ok(id) }
+        }
+    }
+
+    /// Removes the trees and the words files of an index, it must not be listed.
+    fn remove_index_data(&self, id: &str) -> Result<(), Error> {
+        self.inner.drop_tree(&index_name(id))?;
+        self.inner.drop_tree(&updates_name(id))?;
+
+        let words_dir = words_dir(&self.path, id);
+        if words_dir.exists() {
+            fs::remove_dir_all(words_dir)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the data of the indexes that are not listed, it has been
+    /// left by a creation or a deletion interrupted by a crash.
+    fn remove_unlisted_indexes(&self) -> Result<(), Error> {
+        let ids: HashSet<String> = self.indexes_ids()?.into_iter().map(|(_, id)| id).collect();
+
+        let mut unlisted = HashSet::new();
+        for tree_name in self.inner.tree_names() {
+            let id = extract_index_id(&tree_name).or_else(|| extract_updates_id(&tree_name));
+            if let Some(id) = id {
+                if !ids.contains(&id) { unlisted.insert(id); }
+            }
+        }
+
+        let words_dirs = self.path.join("word-index");
+        if words_dirs.exists() {
+            for entry in fs::read_dir(words_dirs)? {
+                let path = entry?.path();
+                if let Some(id) = path.file_name().and_then(|n| n.to_str()) {
+                    if !ids.contains(id) { unlisted.insert(id.to_string()); }
+                }
+            }
+        }
+
+        for id in unlisted {
+            self.remove_index_data(&id)?;
+        }
+
+        Ok(())
+    }
+
+    fn remove_opened(&self, name: &str) -> Option<RawIndex> {
+        let raw_index = self.opened.lease().get(name).cloned();
+
+        self.opened.rcu(|opened| {
+            let mut opened = HashMap::clone(opened);
+            opened.remove(name);
+            opened
+        });
+
+        raw_index
+    }
+}
+
+/// A list of write operations that must be applied to an index tree all at once.
+#[derive(Default)]
+pub struct WriteBatch {
+    operations: Vec<Operation>,
+}
+
+enum Operation {
+    Set(Vec<u8>, Vec<u8>),
+    Del(Vec<u8>),
+}
+
+impl WriteBatch {
+    pub fn new() -> WriteBatch {
+        WriteBatch::default()
+    }
+
+    pub fn set<K, V>(&mut self, key: K, value: V)
+    where K: Into<Vec<u8>>,
+          V: Into<Vec<u8>>,
+    {
+        self.operations.push(Operation::Set(key.into(), value.into()));
+    }
+
+    pub fn del<K>(&mut self, key: K)
+    where K: Into<Vec<u8>>,
+    {
+        self.operations.push(Operation::Del(key.into()));
+    }
+
+    pub fn set_document_attribute<V>(&mut self, id: DocumentId, attr: SchemaAttr, value: V)
+    where V: Into<Vec<u8>>,
+    {
+        self.set(document_key(id, attr), value);
+    }
+
+    pub fn del_document_attribute(&mut self, id: DocumentId, attr: SchemaAttr) {
+        self.del(document_key(id, attr));
+    }
+
+    /// Registers the postings of every word of the index under the given segment.
+    pub fn set_segment_postings(
+        &mut self,
+        segment: u64,
+        index: &PostingsIndex,
+        encoding: PostingsEncoding,
+    ) {
+        index.for_each_word(|word, indexes| {
+            let mut bytes = Vec::new();
+            write_postings(indexes, encoding, &mut bytes);
+            self.set(postings_key(segment, word), bytes);
+        });
+    }
+
+    /// Registers the entries of the new ranked map that differ from the old one.
+    fn set_ranked_map_changes(&mut self, old: &RankedMap, new: &RankedMap) -> Result<(), Error> {
+        for (id, attr) in old.keys() {
+            if !new.contains_key(&(*id, *attr)) {
+                self.del(ranked_key(*id, *attr));
+            }
+        }
+
+        for ((id, attr), number) in new {
+            if old.get(&(*id, *attr)) != Some(number) {
+                self.set(ranked_key(*id, *attr), bincode::serialize(number)?);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Registers the entries of the new facet map that differ from the old one.
+    fn set_facet_map_changes(&mut self, old: &FacetMap, new: &FacetMap) {
+        old.for_each(|id, attr, value| {
+            if !new.contains(id, attr, value) {
+                self.del(facet_key(attr, value, id));
+            }
+        });
+
+        new.for_each(|id, attr, value| {
+            if !old.contains(id, attr, value) {
+                self.set(facet_key(attr, value, id), Vec::new());
+            }
+        });
+    }
+
+    pub fn extend(&mut self, other: WriteBatch) {
+        self.operations.extend(other.operations);
+    }
+
+    /// Removes the operations previously registered on the fields of this document.
+    fn forget_document(&mut self, id: DocumentId) {
+        let prefix = document_prefix(id);
+        self.operations.retain(|operation| {
+            let key = match operation {
+                Operation::Set(key, _) => key,
+                Operation::Del(key) => key,
+            };
+            !key.starts_with(&prefix)
+        });
+    }
+
+    /// Registers the deletion of all the postings of a segment.
+    fn del_segment_postings(&mut self, tree: &sled::Tree, segment: u64) -> sled::Result<()> {
+        let prefix = postings_prefix(segment);
+        for result in tree.range(prefix.clone()..) {
+            let (key, _) = result?;
+            if !key.starts_with(&prefix) { break }
+            self.del(key.to_vec());
+        }
+        Ok(())
+    }
+
+    fn apply(self, tree: &sled::Tree) -> sled::Result<()> {
+        let mut batch = tree.batch();
+        for operation in self.operations {
+            match operation {
+                Operation::Set(key, value) => batch.insert(key, value),
+                Operation::Del(key) => batch.remove(key),
+            }
+        }
+
+        batch.apply()
+    }
+}
+
+#[derive(Clone)]
+pub struct RawIndex {
+    schema: Arc<ArcSwap<Schema>>,
+    word_index: Arc<ArcSwap<WordIndex>>,
+    ranked_map: Arc<ArcSwap<RankedMap>>,
+    facet_map: Arc<ArcSwap<FacetMap>>,
+    postings_encoding: Arc<ArcSwap<PostingsEncoding>>,
+    number_of_documents: Arc<ArcSwap<u64>>,
+    update_lock: Arc<Mutex<()>>,
+    updates: Arc<Updates>,
+    // the segments removed from the word index, their postings are
+    // deleted once the word indexes read by the queries are dropped
+    garbage_segments: Arc<Mutex<Vec<(u64, Weak<Segment>)>>>,
+    words_dir: PathBuf,
+    mmap_words: bool,
+    inner: Arc<sled::Tree>,
+}
+
+/// A handle that does not keep the index alive, it must be upgraded to be used.
+struct WeakRawIndex {
+    schema: Weak<ArcSwap<Schema>>,
+    word_index: Weak<ArcSwap<WordIndex>>,
+    ranked_map: Weak<ArcSwap<RankedMap>>,
+    facet_map: Weak<ArcSwap<FacetMap>>,
+    postings_encoding: Weak<ArcSwap<PostingsEncoding>>,
+    number_of_documents: Weak<ArcSwap<u64>>,
+    update_lock: Weak<Mutex<()>>,
+    updates: Weak<Updates>,
+    garbage_segments: Weak<Mutex<Vec<(u64, Weak<Segment>)>>>,
+    words_dir: PathBuf,
+    mmap_words: bool,
+    inner: Weak<sled::Tree>,
+}
+
+impl WeakRawIndex {
+    fn upgrade(&self) -> Option<RawIndex> {
+        Some(RawIndex {
+            schema: self.schema.upgrade()?,
+            word_index: self.word_index.upgrade()?,
+            ranked_map: self.ranked_map.upgrade()?,
+            facet_map: self.facet_map.upgrade()?,
+            postings_encoding: self.postings_encoding.upgrade()?,
+            number_of_documents: self.number_of_documents.upgrade()?,
+            update_lock: self.update_lock.upgrade()?,
+            updates: self.updates.upgrade()?,
+            garbage_segments: self.garbage_segments.upgrade()?,
+            words_dir: self.words_dir.clone(),
+            mmap_words: self.mmap_words,
+            inner: self.inner.upgrade()?,
+        })
+    }
+}
+
+impl RawIndex {
+    /// Opens an index, the segments that can not be read are
+    /// replaced by empty ones if it is opened to be repaired.
+    fn from_raw(
+        inner: Arc<sled::Tree>,
+        updates: Arc<sled::Tree>,
+        words_dir: PathBuf,
+        mmap_words: bool,
+        repair: bool,
+    ) -> Result<RawIndex, Error>
+    {
+        migrate_legacy_index(&inner)?;
+
+        // the postings of the removed segments that were still read
+        // by the queries of the previous run can now be deleted
+        if let Some(bytes) = inner.get(GARBAGE_SEGMENTS_KEY)? {
+            let ids: Vec<u64> = bincode::deserialize(bytes.as_ref())?;
+            let mut batch = WriteBatch::new();
+            for id in ids {
+                batch.del_segment_postings(&inner, id)?;
+            }
+            batch.del(GARBAGE_SEGMENTS_KEY);
+            batch.apply(&inner)?;
+        }
+
+        let schema = {
+            let bytes = inner.get("schema")?;
+            let bytes = bytes.ok_or(Error::SchemaMissing)?;
+            let schema = Schema::read_from_bin(bytes.as_ref())?;
+            Arc::new(ArcSwap::new(Arc::new(schema)))
+        };
+
+        let word_index = {
+            let start = segment_key(u64::min_value());
+            let end = segment_key(u64::max_value());
+
+            let mut segments = Vec::new();
+            for result in inner.range(start..=end) {
+                let (key, bytes) = result?;
+                let id = match extract_segment_id(&key) {
+                    Some(id) => id,
+                    None => continue,
+                };
+
+                let len = bytes.len();
+                let bytes: Arc<[u8]> = Into::into(bytes);
+                let mut cursor = SharedDataCursor::from_shared_bytes(bytes, 0, len);
+
+                // an empty segment does not hide the documents deleted or updated
+                // after the previous segments, the word index must be rebuilt
+                let mut segment = match Segment::from_shared_data_cursor(&mut cursor) {
+                    Ok(segment) => segment,
+                    Err(e) => {
+                        let error = Error::CorruptedSegment { segment: id, message: e.to_string() };
+                        if !repair { return Err(error) }
+                        error!("{}", error);
+                        Segment::empty(id)
+                    },
+                };
+                segment.id = id;
+
+                // the words of the segment are stored in a file that is memory mapped
+                let path = segment_words_path(&words_dir, id);
+                if path.exists() {
+                    match unsafe { meilidb_core::mmap_words(&path) } {
+                        Ok(words) => segment.words = words,
+                        Err(e) => {
+                            let message = e.to_string();
+                            let error = Error::CorruptedSegment { segment: id, message };
+                            if !repair { return Err(error) }
+                            error!("{}", error);
+                            segment = Segment::empty(id);
+                        },
+                    }
+                }
+
+                segments.push(segment);
+            }
+
+            // the files written by an update that was not committed are removed
+            if words_dir.exists() {
+                for entry in fs::read_dir(&words_dir)? {
+                    let path = entry?.path();
+                    let id = extract_segment_words_id(&path);
+                    if id.map_or(true, |id| segments.iter().all(|s| s.id != id)) {
+                        fs::remove_file(path)?;
+                    }
+                }
+            }
+
+            let word_index = WordIndex::from_segments(segments);
+            Arc::new(ArcSwap::new(Arc::new(word_index)))
+        };
+
+        let ranked_map = Arc::new(ArcSwap::new(Arc::new(read_ranked_map(&inner)?)));
+        let facet_map = Arc::new(ArcSwap::new(Arc::new(read_facet_map(&inner)?)));
+
+        let postings_encoding = {
+            let encoding = match inner.get(POSTINGS_ENCODING_KEY)? {
+                Some(bytes) => bincode::deserialize(bytes.as_ref())?,
+                None => PostingsEncoding::default(),
+            };
+
+            Arc::new(ArcSwap::new(Arc::new(encoding)))
+        };
+
+        let number_of_documents = {
+            let number = match inner.get(NUMBER_OF_DOCUMENTS_KEY)? {
+                Some(bytes) => bincode::deserialize(bytes.as_ref())?,
+                None => {
+                    // the indexes created before the documents were counted
+                    // have an external id for every document
+                    let start = external_id_key(DocumentId(u64::min_value()));
+                    let end = external_id_key(DocumentId(u64::max_value()));
+                    inner.range(start..=end).count() as u64
+                },
+            };
+
+            Arc::new(ArcSwap::new(Arc::new(number)))
+        };
+
+        let update_lock = Arc::new(Mutex::new(()));
+        let (updates, receiver) = Updates::new(updates);
+        let updates = Arc::new(updates);
+        let garbage_segments = Arc::new(Mutex::new(Vec::new()));
+
+        let raw_index = RawIndex {
+            schema,
+            word_index,
+            ranked_map,
+            facet_map,
+            postings_encoding,
+            number_of_documents,
+            update_lock,
+            updates,
+            garbage_segments,
+            words_dir,
+            mmap_words,
+            inner,
+        };
+        spawn_update_system(&raw_index, receiver);
+
+        Ok(raw_index)
+    }
+
+    fn new_from_raw(
+        inner: Arc<sled::Tree>,
+        updates: Arc<sled::Tree>,
+        words_dir: PathBuf,
+        mmap_words: bool,
+        schema: Schema,
+    ) -> Result<RawIndex, Error>
+    {
+        let mut schema_bytes = Vec::new();
+        schema.write_to_bin(&mut schema_bytes)?;
+        inner.insert("schema", schema_bytes)?;
+        let schema = Arc::new(ArcSwap::new(Arc::new(schema)));
+
+        let word_index = Arc::new(ArcSwap::new(Arc::new(WordIndex::default())));
+
+        let ranked_map = Arc::new(ArcSwap::new(Arc::new(RankedMap::default())));
+        let facet_map = Arc::new(ArcSwap::new(Arc::new(FacetMap::default())));
+        let postings_encoding = Arc::new(ArcSwap::new(Arc::new(PostingsEncoding::default())));
+        let number_of_documents = Arc::new(ArcSwap::new(Arc::new(0)));
+        let update_lock = Arc::new(Mutex::new(()));
+        let (updates, receiver) = Updates::new(updates);
+        let updates = Arc::new(updates);
+        let garbage_segments = Arc::new(Mutex::new(Vec::new()));
+
+        let raw_index = RawIndex {
+            schema,
+            word_index,
+            ranked_map,
+            facet_map,
+            postings_encoding,
+            number_of_documents,
+            update_lock,
+            updates,
+            garbage_segments,
+            words_dir,
+            mmap_words,
+            inner,
+        };
+        spawn_update_system(&raw_index, receiver);
+
+        Ok(raw_index)
+    }
+
+    pub fn schema(&self) -> Lease<Arc<Schema>> {
+        self.schema.lease()
+    }
+
+    fn downgrade(&self) -> WeakRawIndex {
+        WeakRawIndex {
+            schema: Arc::downgrade(&self.schema),
+            word_index: Arc::downgrade(&self.word_index),
+            ranked_map: Arc::downgrade(&self.ranked_map),
+            facet_map: Arc::downgrade(&self.facet_map),
+            postings_encoding: Arc::downgrade(&self.postings_encoding),
+            number_of_documents: Arc::downgrade(&self.number_of_documents),
+            update_lock: Arc::downgrade(&self.update_lock),
+            updates: Arc::downgrade(&self.updates),
+            garbage_segments: Arc::downgrade(&self.garbage_segments),
+            words_dir: self.words_dir.clone(),
+            mmap_words: self.mmap_words,
+            inner: Arc::downgrade(&self.inner),
+        }
+    }
+
+    fn close(&self) {
+        self.updates.close();
+    }
+
+    pub fn word_index(&self) -> Lease<Arc<WordIndex>> {
+        self.word_index.lease()
+    }
+
+    pub fn ranked_map(&self) -> Lease<Arc<RankedMap>> {
+        self.ranked_map.lease()
+    }
+
+    pub fn facet_map(&self) -> Lease<Arc<FacetMap>> {
+        self.facet_map.lease()
+    }
+
+    /// The encoding of the postings written by the next updates.
+    pub fn postings_encoding(&self) -> PostingsEncoding {
+        *self.postings_encoding.lease()
+    }
+
+    /// The number of documents of the index, it is updated by every addition and deletion.
+    pub fn number_of_documents(&self) -> u64 {
+        *self.number_of_documents.lease()
+    }
+
+    fn set_postings_encoding(&self, encoding: PostingsEncoding) -> Result<(), Error> {
+        let _lock = self.update_lock.lock().unwrap();
+
+        self.inner.insert(POSTINGS_ENCODING_KEY, bincode::serialize(&encoding)?)?;
+        self.postings_encoding.store(Arc::new(encoding));
+
+        Ok(())
+    }
+
+    /// Atomically writes the batch along with the new word index, ranked map and facet map
+    /// and makes them visible to the readers once everything is persisted.
+    ///
+    /// Only the segments of the word index that are new are written, their postings must
+    /// already be part of the batch, the segments that are not used anymore are deleted
+    /// but their postings are kept until the queries reading them are dropped.
+    pub fn update(
+        &self,
+        mut batch: WriteBatch,
+        mut word_index: Arc<WordIndex>,
+        ranked_map: Arc<RankedMap>,
+        facet_map: Arc<FacetMap>,
+    ) -> Result<(), Error>
+    {
+        let mut mapped_segments = Vec::new();
+        let mut removed_segments = Vec::new();
+        let mut new_garbage = Vec::new();
+
+        {
+            let old_word_index = self.word_index.lease();
+
+            for segment in word_index.segments() {
+                if !old_word_index.segments().iter().any(|s| s.id == segment.id) {
+                    if self.mmap_words && segment.words.len() != 0 {
+                        // the file is written before the commit point
+                        let path = segment_words_path(&self.words_dir, segment.id);
+                        write_segment_words(&path, segment.words.as_fst().as_bytes())?;
+                        batch.set(segment_key(segment.id), segment.without_words().into_bytes());
+                        mapped_segments.push((segment.id, path));
+                    } else {
+                        batch.set(segment_key(segment.id), segment.into_bytes());
+                    }
+                }
+            }
+
+            for segment in old_word_index.segments() {
+                if !word_index.segments().iter().any(|s| s.id == segment.id) {
+                    batch.del(segment_key(segment.id));
+                    removed_segments.push(segment.id);
+                    new_garbage.push((segment.id, Arc::downgrade(segment)));
+                }
+            }
+        }
+
+        // the garbage is persisted to delete it on open if a crash happens
+        let mut garbage_segments = self.garbage_segments.lock().unwrap();
+        if !new_garbage.is_empty() {
+            let ids: Vec<_> = garbage_segments.iter()
+                .chain(&new_garbage)
+                .map(|(id, _)| *id)
+                .collect();
+            batch.set(GARBAGE_SEGMENTS_KEY, bincode::serialize(&ids)?);
+        }
+
+        // only the entries of the maps that changed are written
+        let old_ranked_map = Lease::upgrade(&self.ranked_map());
+        if !Arc::ptr_eq(&old_ranked_map, &ranked_map) {
+            batch.set_ranked_map_changes(&old_ranked_map, &ranked_map)?;
+        }
+
+        let old_facet_map = Lease::upgrade(&self.facet_map());
+        if !Arc::ptr_eq(&old_facet_map, &facet_map) {
+            batch.set_facet_map_changes(&old_facet_map, &facet_map);
+        }
+
+        batch.set(LAST_UPDATE_KEY, bincode::serialize(&SystemTime::now())?);
+
+        self.commit(batch)?;
+        garbage_segments.extend(new_garbage);
+        drop(garbage_segments);
+
+        // the words built in memory are replaced by their memory mapped file
+        for (id, path) in mapped_segments {
+            match unsafe { mmap_words(&path) } {
+                Ok(words) => word_index = Arc::new(word_index.replace_words(id, words)),
+                Err(e) => error!("error while mapping the segment file {:?}; {}", path, e),
+            }
+        }
+
+        // the segments are merged in background by the writer thread
+        let needs_compaction = word_index.needs_compaction();
+
+        self.word_index.store(word_index);
+        self.ranked_map.store(ranked_map);
+        self.facet_map.store(facet_map);
+
+        // the removed files stay readable by the readers that still have them mapped
+        for id in removed_segments {
+            let path = segment_words_path(&self.words_dir, id);
+            if path.exists() {
+                if let Err(e) = fs::remove_file(&path) {
+                    error!("error while removing the segment file {:?}; {}", path, e);
+                }
+            }
+        }
+
+        if let Err(e) = self.collect_garbage_segments() {
+            error!("error while deleting the postings of the removed segments; {}", e);
+        }
+
+        if needs_compaction {
+            self.updates.wake_writer();
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the postings of the removed segments that are not part
+    /// of any word index anymore, returns the number of remaining ones.
+    fn collect_garbage_segments(&self) -> Result<usize, Error> {
+        let mut garbage_segments = self.garbage_segments.lock().unwrap();
+
+        let collected: Vec<_> = garbage_segments.iter()
+            .filter(|(_, segment)| segment.upgrade().is_none())
+            .map(|(id, _)| *id)
+            .collect();
+
+        if collected.is_empty() { return Ok(garbage_segments.len()) }
+
+        let mut batch = WriteBatch::new();
+        for id in &collected {
+            batch.del_segment_postings(&self.inner, *id)?;
+        }
+
+        let remaining: Vec<_> = garbage_segments.iter()
+            .map(|(id, _)| *id)
+            .filter(|id| !collected.contains(id))
+            .collect();
+
+        if remaining.is_empty() {
+            batch.del(GARBAGE_SEGMENTS_KEY);
+        } else {
+            batch.set(GARBAGE_SEGMENTS_KEY, bincode::serialize(&remaining)?);
+        }
+
+        self.commit(batch)?;
+        garbage_segments.retain(|(id, _)| !collected.contains(id));
+
+        Ok(garbage_segments.len())
+    }
+
+    /// Merges the most recent segments of the word index if there is too many of them,
+    /// the writer thread is woken up again if another compaction is needed.
+    fn compact_word_index(&self) -> Result<(), Error> {
+        let _lock = self.update_lock.lock().unwrap();
+
+        let word_index = self.word_index();
+        if !word_index.needs_compaction() { return Ok(()) }
+
+        let start = word_index.compaction_start();
+        let merged = merge_segments(&self.word_index_store(), &word_index.segments()[start..])?;
+
+        let mut batch = WriteBatch::new();
+        let encoding = self.postings_encoding();
+        batch.set_segment_postings(word_index.next_segment_id(), &merged, encoding);
+        let compacted = word_index.compacted(start, merged.map);
+
+        let ranked_map = Lease::upgrade(&self.ranked_map());
+        let facet_map = Lease::upgrade(&self.facet_map());
+        self.update(batch, Arc::new(compacted), ranked_map, facet_map)
+    }
+
+    pub fn word_index_store(&self) -> WordIndexStore {
+        WordIndexStore::from_raw(self)
+    }
+
+    fn commit(&self, batch: WriteBatch) -> Result<(), Error> {
+        batch.apply(&self.inner)?;
+        Ok(())
+    }
+
+    pub fn get_document_attribute(
+        &self,
+        id: DocumentId,
+        attr: SchemaAttr
+    ) -> Result<Option<IVec>, sled::Error>
+    {
+        let key = document_key(id, attr);
+        Ok(self.inner.get(key)?)
+    }
+
+    pub fn get_document_fields(&self, id: DocumentId) -> DocumentFieldsIter {
+        let start = document_key(id, SchemaAttr::min());
+        let end = document_key(id, SchemaAttr::max());
+        DocumentFieldsIter(self.inner.range(start..=end))
+    }
+
+    /// Returns the external id of a document, the value of its identifier attribute.
+    pub fn external_id(&self, id: DocumentId) -> Result<Option<String>, Error> {
+        let bytes = self.inner.get(external_id_key(id))?;
+        Ok(bytes.map(|bytes| String::from_utf8_lossy(bytes.as_ref()).into_owned()))
+    }
+
+    /// Returns the internal id of the document with the given external id, if it exists.
+    pub fn document_id(&self, external_id: &str) -> Result<Option<DocumentId>, Error> {
+        let id = compute_document_id(external_id);
+        match self.external_id(id)? {
+            Some(ref stored) if stored == external_id => Ok(Some(id)),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn documents_fields(&self) -> DocumentFieldsIter {
+        let start = document_key(DocumentId(u64::min_value()), SchemaAttr::min());
+        let end = document_key(DocumentId(u64::max_value()), SchemaAttr::max());
+        DocumentFieldsIter(self.inner.range(start..=end))
+    }
+
+    /// Returns the ids of the documents which have stored fields, in order.
+    pub fn documents_ids(&self) -> DocumentsIdsIter {
+        let start = document_key(DocumentId(u64::min_value()), SchemaAttr::min());
+        DocumentsIdsIter { tree: &self.inner, start: Some(start) }
+    }
+}
+
+pub struct DocumentFieldsIter<'a>(sled::Iter<'a>);
+
+impl<'a> Iterator for DocumentFieldsIter<'a> {
+    type Item = Result<(DocumentId, SchemaAttr, IVec), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.0.next() {
+            Some(Ok((key, value))) => {
+                match extract_document_key(&key) {
+                    Ok((id, attr)) => Some(Ok((id, attr, value))),
+                    Err(_) => Some(Err(Error::CorruptedDocumentKey(key.to_vec()))),
+                }
+            },
+            Some(Err(e)) => Some(Err(Error::SledError(e))),
+            None => None,
+        }
+    }
+}
+
+/// Seeks to the fields of the next document instead of reading
+/// all the fields of the current one.
+pub struct DocumentsIdsIter<'a> {
+    tree: &'a sled::Tree,
+    start: Option<Vec<u8>>,
+}
+
+impl<'a> Iterator for DocumentsIdsIter<'a> {
+    type Item = Result<DocumentId, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.start.take()?;
+        let end = document_key(DocumentId(u64::max_value()), SchemaAttr::max());
+
+        let key = match self.tree.range(start..=end).next()? {
+            Ok((key, _)) => key,
+            Err(e) => return Some(Err(Error::SledError(e))),
+        };
+
+        match extract_document_key(&key) {
+            Ok((id, _)) => {
+                let next_id = id.0.checked_add(1).map(DocumentId);
+                self.start = next_id.map(|id| document_key(id, SchemaAttr::min()));
+                Some(Ok(id))
+            },
+            Err(_) => {
+                // the smallest key following the corrupted one
+                let mut next = key.to_vec();
+                next.push(0);
+                self.start = Some(next);
+                Some(Err(Error::CorruptedDocumentKey(key.to_vec())))
+            },
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Index(RawIndex);
+
+impl Index {
+    pub fn query_builder(&self) -> QueryBuilder<WordIndexStore> {
+        QueryBuilder::new(self.0.word_index_store())
+    }
+
+    pub fn query_builder_with_criteria<'c>(
+        &self,
+        criteria: Criteria<'c>,
+    ) -> QueryBuilder<'c, WordIndexStore>
+    {
+        QueryBuilder::with_criteria(self.0.word_index_store(), criteria)
+    }
+
+    pub fn schema(&self) -> Lease<Arc<Schema>> {
+        self.0.schema()
+    }
+
+    /// Replaces the schema of this index, the attributes keep their `SchemaAttr`.
+    ///
+    /// Only the changed attributes are reindexed or removed from the stores, an attribute
+    /// can only become indexed, ranked or faceted if its values were already stored.
+    pub fn update_schema(&self, schema: Schema) -> Result<(), Error> {
+        apply_schema_update(&self.0, &schema)
+    }
+
+    pub fn word_index(&self) -> Lease<Arc<WordIndex>> {
+        self.0.word_index()
+    }
+
+    /// Checks that the word index and the stored documents of this index can be read,
+    /// a corrupted word index can be fixed by rebuilding it with `reindex`.
+    pub fn verify(&self) -> Result<IntegrityReport, Error> {
+        verify_index(&self.0)
+    }
+
+    /// Replaces the word index and the ranked map by new ones built from the stored documents,
+    /// the progress function is called after each document.
+    ///
+    /// Every indexed, ranked or faceted attribute must also be a stored one.
+    pub fn reindex<F>(&self, progress: F) -> Result<(), Error>
+    where F: FnMut(ReindexProgress),
+    {
+        reindex_index(&self.0, progress)
+    }
+
+    pub fn ranked_map(&self) -> Lease<Arc<RankedMap>> {
+        self.0.ranked_map()
+    }
+
+    pub fn facet_map(&self) -> Lease<Arc<FacetMap>> {
+        self.0.facet_map()
+    }
+
+    /// Computes the number of documents, words and postings of this index
+    /// along with the size of its stores and the time of its last update.
+    pub fn stats(&self) -> Result<IndexStats, Error> {
+        index_stats(&self.0)
+    }
+
+    pub fn postings_encoding(&self) -> PostingsEncoding {
+        self.0.postings_encoding()
+    }
+
+    /// Changes the encoding of the postings written by the next updates, the postings
+    /// already written keep their encoding until they are compacted or reindexed.
+    pub fn set_postings_encoding(&self, encoding: PostingsEncoding) -> Result<(), Error> {
+        self.0.set_postings_encoding(encoding)
+    }
+
+    /// Documents added by this update replace any previous version of them.
+    pub fn documents_addition(&self) -> DocumentsAddition {
+        let index = self.0.clone();
+        DocumentsAddition::from_raw(index)
+    }
+
+    /// Documents added by this update only rewrite the attributes they contain,
+    /// the other attributes of the previous version of them are kept.
+    pub fn documents_partial_addition(&self) -> DocumentsAddition {
+        let index = self.0.clone();
+        DocumentsAddition::partial_from_raw(index)
+    }
+
+    pub fn documents_deletion(&self) -> DocumentsDeletion {
+        let index = self.0.clone();
+        DocumentsDeletion::from_raw(index)
+    }
+
+    /// Deletes the document with the given external id right away,
+    /// returns `false` if no such document exists.
+    pub fn delete_document_by_key(&self, external_id: &str) -> Result<bool, Error> {
+        let mut deletion = self.documents_deletion();
+        if !deletion.delete_document_by_key(external_id)? {
+            return Ok(false)
+        }
+
+        deletion.finalize()?;
+
+        Ok(true)
+    }
+
+    pub fn enqueue_documents_addition<D, I>(&self, documents: I) -> Result<u64, Error>
+    where D: serde::Serialize,
+          I: IntoIterator<Item=D>,
+    {
+        let documents = documents_to_values(documents)?;
+        self.0.updates.enqueue(&Update::DocumentsAddition(documents))
+    }
+
+    pub fn enqueue_documents_partial_addition<D, I>(&self, documents: I) -> Result<u64, Error>
+    where D: serde::Serialize,
+          I: IntoIterator<Item=D>,
+    {
+        let documents = documents_to_values(documents)?;
+        self.0.updates.enqueue(&Update::DocumentsPartialAddition(documents))
+    }
+
+    pub fn enqueue_documents_deletion<I>(&self, documents: I) -> Result<u64, Error>
+    where I: IntoIterator<Item=DocumentId>,
+    {
+        let documents = documents.into_iter().collect();
+        self.0.updates.enqueue(&Update::DocumentsDeletion(documents))
+    }
+
+    pub fn update_status(&self, update_id: u64) -> Result<Option<UpdateStatus>, Error> {
+        self.0.updates.status(update_id)
+    }
+
+    /// Removes the results of the processed updates up to the given one included,
+    /// they are kept until then. Returns the number of results removed.
+    pub fn clear_update_results(&self, up_to: u64) -> Result<usize, Error> {
+        self.0.updates.clear_results(up_to)
+    }
+
+    /// Returns a channel on which an event is sent each time
+    /// an update has been processed on this index.
+    pub fn subscribe(&self) -> Receiver<UpdateEvent> {
+        self.0.updates.subscribe()
+    }
+
+    pub fn external_id(&self, id: DocumentId) -> Result<Option<String>, Error> {
+        self.0.external_id(id)
+    }
+
+    /// Exports the schema and the stored documents in a
+    /// portable format that `Database::restore_index` reads back.
+    ///
+    /// The returned report lists the documents that could not be exported.
+    pub fn dump<W: Write>(&self, writer: W) -> Result<DumpReport, Error> {
+        dump_index(self, writer)
+    }
+
+    pub fn document_id(&self, external_id: &str) -> Result<Option<DocumentId>, Error> {
+        self.0.document_id(external_id)
+    }
+
+    pub fn document_by_key<T>(
+        &self,
+        fields: Option<&HashSet<&str>>,
+        external_id: &str,
+    ) -> Result<Option<T>, Error>
+    where T: de::DeserializeOwned,
+    {
+        match self.0.document_id(external_id)? {
+            Some(id) => Ok(self.document(fields, id)?),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the number of documents of this index, it is maintained
+    /// by the updates and does not require to read the stored documents.
+    pub fn number_of_documents(&self) -> u64 {
+        self.0.number_of_documents()
+    }
+
+    /// Returns the stored documents of this index ordered by `DocumentId`, skipping
+    /// the first `offset` ones and returning at most `limit` of them.
+    ///
+    /// Only the given fields are returned if any, like with `document`.
+    pub fn documents<T>(
+        &self,
+        offset: usize,
+        limit: usize,
+        fields: Option<&HashSet<&str>>,
+    ) -> Result<Vec<T>, Error>
+    where T: de::DeserializeOwned,
+    {
+        let mut documents = Vec::new();
+        let mut skipped = 0;
+
+        for result in self.0.documents_ids() {
+            if documents.len() >= limit { break }
+
+            let id = match result {
+                Ok(id) => id,
+                // the corrupted keys are reported by the verification
+                Err(Error::CorruptedDocumentKey(_)) => continue,
+                Err(e) => return Err(e),
+            };
+
+            if skipped < offset {
+                skipped += 1;
+                continue
+            }
+
+            if let Some(document) = self.document(fields, id)? {
+                documents.push(document);
+            }
+        }
+
+        Ok(documents)
+    }
+
+    pub fn document<T>(
+        &self,
+        fields: Option<&HashSet<&str>>,
+        id: DocumentId,
+    ) -> Result<Option<T>, RmpError>
+    where T: de::DeserializeOwned,
+    {
+        let fields = match fields {
+            Some(fields) => {
+                let iter = fields.iter().filter_map(|n| self.0.schema().attribute(n));
+                Some(HashSet::from_iter(iter))
+            },
+            None => None,
+        };
+
+        // a document without any stored field does not exist
+        if self.0.get_document_fields(id).next().is_none() {
+            return Ok(None)
+        }
+
+        let mut deserializer = Deserializer {
+            document_id: id,
+            raw_index: &self.0,
+            fields: fields.as_ref(),
+        };
+
+        T::deserialize(&mut deserializer).map(Some)
+    }
+}
+
+fn documents_to_values<D, I>(documents: I) -> Result<Vec<serde_json::Value>, Error>
+where D: serde::Serialize,
+      I: IntoIterator<Item=D>,
+{
+    let mut values = Vec::new();
+    for document in documents {
+        values.push(serde_json::to_value(document)?);
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use serde_json::json;
+    use crate::schema::{SchemaBuilder, STORED, INDEXED, RANKED};
+
+    pub fn simple_schema() -> Schema {
+        let mut builder = SchemaBuilder::with_identifier("id");
+        builder.new_attribute("id", STORED);
+        builder.new_attribute("title", STORED | INDEXED);
+        builder.new_attribute("rank", STORED | RANKED);
+        builder.build()
+    }
+
+    pub fn reopen_index(database: &Database, name: &str) -> Index {
+        if let Some(raw_index) = database.remove_opened(name) {
+            raw_index.close();
+        }
+        database.open_index(name).unwrap().unwrap()
+    }
+
+    #[test]
+    fn delete_and_rename_indexes() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+
+        let index = database.create_index("movies".to_string(), simple_schema()).unwrap();
+        let mut addition = index.documents_addition();
+        addition.update_document(json!({ "id": 1, "title": "hello", "rank": 4 })).unwrap();
+        addition.finalize().unwrap();
+
+        database.create_index("books".to_string(), simple_schema()).unwrap();
+        assert_eq!(database.indexes().unwrap(), vec!["books", "movies"]);
+
+        // the data of the index is not moved
+        let mut tree_names = database.inner.tree_names();
+        assert!(database.rename_index("movies", "films").unwrap());
+        let mut new_tree_names = database.inner.tree_names();
+        tree_names.sort();
+        new_tree_names.sort();
+        assert_eq!(tree_names, new_tree_names);
+
+        assert!(!database.rename_index("movies", "films").unwrap());
+        match database.rename_index("books", "films") {
+            Err(Error::IndexAlreadyExists) => (),
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        assert_eq!(database.indexes().unwrap(), vec!["books", "films"]);
+        assert!(database.open_index("movies").unwrap().is_none());
+
+        let films = database.open_index("films").unwrap().unwrap();
+        assert_eq!(films.query_builder().query("hello", 0..10).unwrap().hits.len(), 1);
+        assert_eq!(films.number_of_documents(), 1);
+
+        // the handles of the renamed index are still valid
+        let mut addition = index.documents_addition();
+        addition.update_document(json!({ "id": 2, "title": "world", "rank": 5 })).unwrap();
+        addition.finalize().unwrap();
+        assert_eq!(films.number_of_documents(), 2);
+
+        assert!(database.delete_index("books").unwrap());
+        assert!(!database.delete_index("books").unwrap());
+        assert!(database.open_index("books").unwrap().is_none());
+        assert_eq!(database.indexes().unwrap(), vec!["films"]);
+    }
+
+    #[test]
+    fn remove_unlisted_indexes() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+        database.create_index("movies".to_string(), simple_schema()).unwrap();
+
+        // the data of an index whose creation was interrupted before it was listed
+        let tree = database.inner.open_tree(index_name("films")).unwrap();
+        tree.insert("schema", Vec::new()).unwrap();
+        database.inner.open_tree(updates_name("films")).unwrap();
+        let films_words_dir = words_dir(dir.path(), "films");
+        write_segment_words(&segment_words_path(&films_words_dir, 0), &[]).unwrap();
+
+        database.remove_unlisted_indexes().unwrap();
+
+        let tree_names = database.inner.tree_names();
+        assert!(!tree_names.contains(&index_name("films")));
+        assert!(!tree_names.contains(&updates_name("films")));
+        assert!(tree_names.contains(&index_name(&database.indexes_ids().unwrap()["movies"])));
+        assert!(!films_words_dir.exists());
+        assert_eq!(database.indexes().unwrap(), vec!["movies"]);
+    }
+
+    #[test]
+    fn legacy_indexes_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+
+        let index = database.create_index("films".to_string(), simple_schema()).unwrap();
+        let mut addition = index.documents_addition();
+        addition.update_document(json!({ "id": 1, "title": "hello", "rank": 4 })).unwrap();
+        addition.finalize().unwrap();
+        database.remove_opened("films").unwrap().close();
+
+        // the indexes were listed by name and their trees were named after them
+        let id = database.indexes_ids().unwrap()["films"].clone();
+        let tree = database.inner.open_tree(index_name(&id)).unwrap();
+        copy_tree(&tree, &database.inner.open_tree(index_name("films")).unwrap()).unwrap();
+        let updates = database.inner.open_tree(updates_name(&id)).unwrap();
+        copy_tree(&updates, &database.inner.open_tree(updates_name("films")).unwrap()).unwrap();
+        database.remove_index_data(&id).unwrap();
+
+        database.metadata.remove(INDEX_IDS_KEY).unwrap();
+        let names = bincode::serialize(&vec!["films".to_string()]).unwrap();
+        database.metadata.insert(INDEXES_KEY, names).unwrap();
+
+        let films = database.open_index("films").unwrap().unwrap();
+        assert_eq!(films.number_of_documents(), 1);
+        assert!(database.metadata.get(INDEXES_KEY).unwrap().is_none());
+
+        // the name of the index is its id, it is not reused by a new index
+        assert!(database.rename_index("films", "movies").unwrap());
+        database.create_index("films".to_string(), simple_schema()).unwrap();
+
+        let ids = database.indexes_ids().unwrap();
+        assert_eq!(ids["movies"], "films");
+        assert_ne!(ids["films"], "films");
+        assert_eq!(database.open_index("movies").unwrap().unwrap().number_of_documents(), 1);
+    }
+
+    #[test]
+    fn snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path().join("database")).unwrap();
+
+        let index = database.create_index("test".to_string(), simple_schema()).unwrap();
+        let mut addition = index.documents_addition();
+        addition.update_document(json!({ "id": "abc", "title": "hello", "rank": 1 })).unwrap();
+        addition.finalize().unwrap();
+
+        // the pending updates are part of the snapshot
+        index.0.close();
+        let update_id = index.enqueue_documents_addition(vec![
+            json!({ "id": "def", "title": "goodbye", "rank": 2 }),
+        ]).unwrap();
+
+        let snapshot_path = dir.path().join("snapshot");
+        database.snapshot(&snapshot_path).unwrap();
+        assert!(database.snapshot(&snapshot_path).is_err());
+
+        // the documents added after the snapshot are not part of it
+        let mut addition = index.documents_addition();
+        addition.update_document(json!({ "id": "ghi", "title": "hello", "rank": 3 })).unwrap();
+        addition.finalize().unwrap();
+        assert_eq!(index.query_builder().query("hello", 0..10).unwrap().hits.len(), 2);
+
+        // sled releases the files of the snapshot database in background,
+        // opening them while they are still locked panics
+        let snapshot = (0..100)
+            .find_map(|_| {
+                std::thread::sleep(Duration::from_millis(10));
+                std::panic::catch_unwind(|| Database::start_default(&snapshot_path)).ok()
+            })
+            .unwrap()
+            .unwrap();
+        assert_eq!(snapshot.indexes().unwrap(), vec!["test".to_string()]);
+
+        let index = snapshot.open_index("test").unwrap().unwrap();
+        assert_eq!(index.query_builder().query("hello", 0..10).unwrap().hits.len(), 1);
+
+        loop {
+            match index.update_status(update_id).unwrap() {
+                Some(UpdateStatus::Processed(_)) => break,
+                Some(UpdateStatus::Failed(result)) => panic!("update failed {:?}", result),
+                _ => std::thread::sleep(Duration::from_millis(1)),
+            }
+        }
+
+        assert_eq!(index.number_of_documents(), 2);
+        let document: Option<serde_json::Value> = index.document_by_key(None, "def").unwrap();
+        assert_eq!(document, Some(json!({ "id": "def", "title": "goodbye", "rank": 2 })));
+    }
+
+    #[test]
+    fn queries_during_compaction() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+        let index = database.create_index("test".to_string(), simple_schema()).unwrap();
+
+        let mut addition = index.documents_addition();
+        addition.update_document(json!({ "id": "abc", "title": "hello", "rank": 1 })).unwrap();
+        addition.finalize().unwrap();
+
+        // the postings of the removed segment are kept while a query can read them
+        let builder = index.query_builder();
+        let old_segment = index.word_index().segments()[0].id;
+        index.reindex(|_| ()).unwrap();
+        assert!(index.word_index().segments().iter().all(|s| s.id != old_segment));
+        assert!(index.0.inner.scan_prefix(postings_prefix(old_segment)).next().is_some());
+        assert_eq!(builder.query("hello", 0..10).unwrap().hits.len(), 1);
+
+        assert_eq!(index.0.collect_garbage_segments().unwrap(), 0);
+        assert!(index.0.inner.scan_prefix(postings_prefix(old_segment)).next().is_none());
+        assert!(index.0.inner.get(GARBAGE_SEGMENTS_KEY).unwrap().is_none());
+
+        let reader = {
+            let index = index.clone();
+            std::thread::spawn(move || {
+                for _ in 0..200 {
+                    let result = index.query_builder().query("hello", 0..10).unwrap();
+                    assert_eq!(result.hits.len(), 1);
+                }
+            })
+        };
+
+        // every addition pushes a segment, they are compacted by the writer thread
+        for i in 0..50 {
+            let mut addition = index.documents_addition();
+            let document = json!({ "id": format!("id{}", i), "title": "world", "rank": i });
+            addition.update_document(document).unwrap();
+            addition.finalize().unwrap();
+        }
+
+        reader.join().unwrap();
+        assert_eq!(index.query_builder().query("world", 0..100).unwrap().hits.len(), 50);
+
+        // the garbage left by a crash is deleted on open
+        let builder = index.query_builder();
+        let old_segments: Vec<_> = index.word_index().segments().iter().map(|s| s.id).collect();
+        index.reindex(|_| ()).unwrap();
+        assert!(index.0.inner.get(GARBAGE_SEGMENTS_KEY).unwrap().is_some());
+        drop(builder);
+
+        let index = reopen_index(&database, "test");
+        assert!(index.0.inner.get(GARBAGE_SEGMENTS_KEY).unwrap().is_none());
+        for id in old_segments {
+            assert!(index.0.inner.scan_prefix(postings_prefix(id)).next().is_none());
+        }
+        assert_eq!(index.query_builder().query("hello", 0..10).unwrap().hits.len(), 1);
+    }
+
+    #[test]
+    fn corrupted_segment() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+        let index = database.create_index("test".to_string(), simple_schema()).unwrap();
+
+        let mut addition = index.documents_addition();
+        addition.update_document(json!({ "id": "abc", "title": "hello", "rank": 1 })).unwrap();
+        addition.update_document(json!({ "id": "def", "title": "hello", "rank": 2 })).unwrap();
+        addition.finalize().unwrap();
+
+        let mut deletion = index.documents_deletion();
+        deletion.delete_document_by_key("def").unwrap();
+        deletion.finalize().unwrap();
+
+        // the segment hiding the deleted document can not be read
+        let segments: Vec<_> = index.word_index().segments().iter().map(|s| s.id).collect();
+        assert_eq!(segments.len(), 2);
+        index.0.inner.insert(segment_key(segments[1]), &b"corrupted"[..]).unwrap();
+
+        if let Some(raw_index) = database.remove_opened("test") {
+            raw_index.close();
+        }
+
+        match database.open_index("test") {
+            Err(Error::CorruptedSegment { segment, .. }) => assert_eq!(segment, segments[1]),
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+
+        let mut progress = Vec::new();
+        let index = database.repair_index("test", |p| progress.push(p)).unwrap().unwrap();
+        assert_eq!(progress.last().map(|p| p.indexed_documents), Some(1));
+        assert!(index.verify().unwrap().is_ok());
+
+        let result = index.query_builder().query("hello", 0..10).unwrap();
+        assert_eq!(result.hits.len(), 1);
+        assert_eq!(index.external_id(result.hits[0].id).unwrap(), Some("abc".to_string()));
+
+        assert!(database.repair_index("unknown", |_| ()).unwrap().is_none());
+        assert!(reopen_index(&database, "test").verify().unwrap().is_ok());
+    }
+
+    #[test]
+    fn mmap_word_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = DatabaseOptions { mmap_word_index: true };
+        let database = Database::start_with_options(dir.path(), options).unwrap();
+        let index = database.create_index("test".to_string(), simple_schema()).unwrap();
+
+        let mut addition = index.documents_addition();
+        addition.update_document(json!({ "id": "abc", "title": "hello", "rank": 1 })).unwrap();
+        addition.update_document(json!({ "id": "def", "title": "world", "rank": 2 })).unwrap();
+        addition.finalize().unwrap();
+
+        // the words are not stored in the tree but in a file
+        let segment = index.word_index().segments()[0].id;
+        let path = segment_words_path(&index.0.words_dir, segment);
+        assert!(path.exists());
+        let bytes = index.0.inner.get(segment_key(segment)).unwrap().unwrap();
+        assert_eq!(Segment::from_bytes(bytes.to_vec()).unwrap().words.len(), 0);
+        assert_eq!(index.query_builder().query("hello", 0..10).unwrap().hits.len(), 1);
+
+        // the files of the segments that were not committed are removed on open
+        let stray = segment_words_path(&index.0.words_dir, segment + 10);
+        fs::write(&stray, b"not committed").unwrap();
+
+        let index = reopen_index(&database, "test");
+        assert!(!stray.exists());
+        assert_eq!(index.query_builder().query("hello", 0..10).unwrap().hits.len(), 1);
+        assert_eq!(index.query_builder().query("world", 0..10).unwrap().hits.len(), 1);
+
+        // the file of a removed segment is removed
+        index.reindex(|_| ()).unwrap();
+        assert!(!path.exists());
+        let segment = index.word_index().segments()[0].id;
+        assert!(segment_words_path(&index.0.words_dir, segment).exists());
+        assert_eq!(index.query_builder().query("world", 0..10).unwrap().hits.len(), 1);
+    }
+
+    #[test]
+    fn documents_pagination() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+        let index = database.create_index("test".to_string(), simple_schema()).unwrap();
+
+        let mut addition = index.documents_addition();
+        for i in 0..10 {
+            let document = json!({ "id": format!("id{}", i), "title": "hello", "rank": i });
+            addition.update_document(document).unwrap();
+        }
+        addition.finalize().unwrap();
+
+        let mut deletion = index.documents_deletion();
+        deletion.delete_document_by_key("id3").unwrap();
+        deletion.finalize().unwrap();
+        assert_eq!(index.number_of_documents(), 9);
+
+        let all: Vec<serde_json::Value> = index.documents(0, 100, None).unwrap();
+        assert_eq!(all.len(), 9);
+        assert!(all.iter().all(|d| d["id"] != "id3"));
+
+        // the pages follow each other without any gap or overlap
+        let mut pages = Vec::new();
+        for offset in (0..10).step_by(4) {
+            let page: Vec<serde_json::Value> = index.documents(offset, 4, None).unwrap();
+            assert!(page.len() <= 4);
+            pages.extend(page);
+        }
+        assert_eq!(pages, all);
+
+        let page: Vec<serde_json::Value> = index.documents(9, 4, None).unwrap();
+        assert!(page.is_empty());
+        let page: Vec<serde_json::Value> = index.documents(0, 0, None).unwrap();
+        assert!(page.is_empty());
+
+        let fields: HashSet<_> = ["id"].iter().cloned().collect();
+        let page: Vec<serde_json::Value> = index.documents(2, 3, Some(&fields)).unwrap();
+        let ids: Vec<_> = all[2..5].iter().map(|d| json!({ "id": d["id"] })).collect();
+        assert_eq!(page, ids);
+
+        // the count is persisted
+        let index = reopen_index(&database, "test");
+        assert_eq!(index.number_of_documents(), 9);
+    }
+}
@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::indexer::Indexer as RawIndexer;
+use crate::serde::{Indexer, ConvertToNumber, ConvertToFacets};
+use crate::{RankedMap, FacetMap};
+use super::{Error, RawIndex, WriteBatch};
+
+/// Sent to the progress function of a reindexation after each document.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ReindexProgress {
+    pub indexed_documents: usize,
+    pub total_documents: usize,
+}
+
+/// Builds a new word index, ranked map and facet map from the stored documents and replaces
+/// the previous ones at once, the updates are blocked during the reindexation.
+pub fn reindex_index<F>(index: &RawIndex, mut progress: F) -> Result<(), Error>
+where F: FnMut(ReindexProgress),
+{
+    let _lock = index.update_lock.lock().unwrap();
+    let schema = index.schema();
+
+    // the values of an attribute must be stored to be indexed, ranked or faceted again
+    for (name, _, props) in schema.iter() {
+        let rebuilt = props.is_indexed() || props.is_ranked() || props.is_faceted();
+        if rebuilt && !props.is_stored() {
+            return Err(Error::AttributeNotStored(name.to_owned()))
+        }
+    }
+
+    let total_documents = index.number_of_documents() as usize;
+
+    let mut indexer = RawIndexer::from_schema(&schema);
+    let mut ranked_map = RankedMap::default();
+    let mut facet_map = FacetMap::default();
+    let mut indexed_documents = 0;
+    let mut last_id = None;
+
+    for result in index.documents_fields() {
+        let (document_id, attr, value) = match result {
+            Ok(field) => field,
+            // a field with a corrupted key can not be attributed to a document
+            Err(Error::CorruptedDocumentKey(_)) => continue,
+            Err(e) => return Err(e),
+        };
+
+        // the fields of a document are contiguous
+        if last_id != Some(document_id) {
+            if last_id.is_some() {
+                indexed_documents += 1;
+                progress(ReindexProgress { indexed_documents, total_documents });
+            }
+            last_id = Some(document_id);
+        }
+
+        let props = schema.props(attr);
+        if !props.is_indexed() && !props.is_ranked() && !props.is_faceted() { continue }
+
+        let value: serde_json::Value = rmp_serde::from_slice(value.as_ref())?;
+
+        if props.is_indexed() {
+            let indexer = Indexer { attribute: attr, indexer: &mut indexer, document_id };
+            value.serialize(indexer)?;
+        }
+
+        if props.is_ranked() {
+            let number = value.serialize(ConvertToNumber)?;
+            ranked_map.insert((document_id, attr), number);
+        }
+
+        if props.is_faceted() {
+            for facet in value.serialize(ConvertToFacets)? {
+                facet_map.insert(document_id, attr, facet);
+            }
+        }
+    }
+
+    if last_id.is_some() {
+        indexed_documents += 1;
+        progress(ReindexProgress { indexed_documents, total_documents });
+    }
+
+    let word_index = index.word_index();
+    let delta_index = indexer.build();
+    let encoding = index.postings_encoding();
+
+    // all the previous segments and their postings are removed
+    let mut batch = WriteBatch::new();
+    batch.set_segment_postings(word_index.next_segment_id(), &delta_index, encoding);
+    let word_index = word_index.replace(delta_index.map);
+
+    index.update(batch, Arc::new(word_index), Arc::new(ranked_map), Arc::new(facet_map))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::Database;
+    use crate::database::tests::{simple_schema, reopen_index};
+    use crate::schema::{SchemaBuilder, STORED, INDEXED};
+
+    #[test]
+    fn reindex() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+        let index = database.create_index("test".to_string(), simple_schema()).unwrap();
+
+        let documents = vec![
+            json!({ "id": "abc", "title": "hello", "rank": 1 }),
+            json!({ "id": "def", "title": "hello world", "rank": 2 }),
+            json!({ "id": "ghi", "title": "world", "rank": 3 }),
+        ];
+
+        // every addition pushes a segment
+        for document in documents {
+            let mut addition = index.documents_addition();
+            addition.update_document(document).unwrap();
+            addition.finalize().unwrap();
+        }
+
+        let mut deletion = index.documents_deletion();
+        deletion.delete_document_by_key("ghi").unwrap();
+        deletion.finalize().unwrap();
+
+        let ranked_map = RankedMap::clone(&index.ranked_map());
+        assert_eq!(index.word_index().segments().len(), 4);
+
+        let mut progress = Vec::new();
+        index.reindex(|p| progress.push(p)).unwrap();
+
+        let expected: Vec<_> = (1..=2)
+            .map(|indexed_documents| ReindexProgress { indexed_documents, total_documents: 2 })
+            .collect();
+        assert_eq!(progress, expected);
+
+        assert_eq!(index.word_index().segments().len(), 1);
+        assert_eq!(*index.ranked_map(), ranked_map);
+        assert_eq!(index.query_builder().query("hello", 0..10).unwrap().hits.len(), 2);
+        assert_eq!(index.query_builder().query("world", 0..10).unwrap().hits.len(), 1);
+        assert!(index.verify().unwrap().is_ok());
+
+        // the rebuilt word index is the one that is opened again
+        let index = reopen_index(&database, "test");
+        assert_eq!(index.word_index().segments().len(), 1);
+        assert_eq!(index.query_builder().query("world", 0..10).unwrap().hits.len(), 1);
+
+        // an indexed attribute that is not stored can not be indexed again
+        let mut builder = SchemaBuilder::with_identifier("id");
+        builder.new_attribute("id", STORED);
+        builder.new_attribute("title", INDEXED);
+        let index = database.create_index("unstored".to_string(), builder.build()).unwrap();
+
+        match index.reindex(|_| ()) {
+            Err(Error::AttributeNotStored(name)) => assert_eq!(name, "title"),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use std::sync::Arc;
+
+use arc_swap::Lease;
+use meilidb_core::merge_segments;
+use sdset::SetBuf;
+use serde::Serialize;
+
+use crate::indexer::Indexer as RawIndexer;
+use crate::serde::{Indexer, ConvertToNumber, ConvertToFacets};
+use crate::{Schema, RankedMap, FacetMap};
+use super::{Error, RawIndex, WriteBatch};
+
+/// Replaces the schema of the index by its evolution into the new one,
+/// the stores are only updated for the attributes whose properties changed.
+pub fn apply_schema_update(index: &RawIndex, new_schema: &Schema) -> Result<(), Error> {
+    let _lock = index.update_lock.lock().unwrap();
+
+    let old_schema = index.schema.load();
+    // an inferred identifier can not be replaced
+    if let (Some(old), Some(new)) = (old_schema.identifier(), new_schema.identifier()) {
+        if old != new { return Err(Error::SchemaDiffer) }
+    }
+
+    let schema = old_schema.evolve(new_schema);
+
+    let mut unindexed = Vec::new();
+    let mut unranked = Vec::new();
+    let mut unfaceted = Vec::new();
+    let mut unstored = Vec::new();
+    let mut reindexed = Vec::new();
+    let mut reranked = Vec::new();
+    let mut refaceted = Vec::new();
+
+    for (name, attr, old_props) in old_schema.iter() {
+        let new_props = schema.props(attr);
+
+        if old_props.is_indexed() && !new_props.is_indexed() { unindexed.push(attr.0) }
+        if old_props.is_ranked() && !new_props.is_ranked() { unranked.push(attr) }
+        if old_props.is_faceted() && !new_props.is_faceted() { unfaceted.push(attr) }
+        if old_props.is_stored() && !new_props.is_stored() { unstored.push(attr) }
+
+        let indexed = !old_props.is_indexed() && new_props.is_indexed();
+        let ranked = !old_props.is_ranked() && new_props.is_ranked();
+        let faceted = !old_props.is_faceted() && new_props.is_faceted();
+
+        // a new word limit requires the attribute to be removed and indexed again
+        let relimited = old_props.is_indexed() && new_props.is_indexed()
+            && old_props.word_limit() != new_props.word_limit();
+
+        if (indexed || ranked || faceted || relimited) && !old_props.is_stored() {
+            return Err(Error::AttributeNotStored(name.to_owned()))
+        }
+
+        if relimited { unindexed.push(attr.0) }
+        if indexed || relimited { reindexed.push(attr) }
+        if ranked { reranked.push(attr) }
+        if faceted { refaceted.push(attr) }
+    }
+
+    let mut batch = WriteBatch::new();
+    let mut indexer = RawIndexer::from_schema(&schema);
+    let mut ranked_map = RankedMap::clone(&index.ranked_map());
+    ranked_map.retain(|(_, attr), _| !unranked.contains(attr));
+    let mut facet_map = FacetMap::clone(&index.facet_map());
+    facet_map.remove_attributes(&unfaceted);
+
+    let refilled = !reindexed.is_empty() || !reranked.is_empty() || !refaceted.is_empty();
+    if !unstored.is_empty() || refilled {
+        for result in index.documents_fields() {
+            let (document_id, attr, value) = result?;
+
+            if unstored.contains(&attr) {
+                batch.del_document_attribute(document_id, attr);
+            }
+
+            let refill = reindexed.contains(&attr)
+                || reranked.contains(&attr)
+                || refaceted.contains(&attr);
+            if !refill { continue }
+
+            let value: serde_json::Value = rmp_serde::from_slice(value.as_ref())?;
+
+            if reindexed.contains(&attr) {
+                let indexer = Indexer { attribute: attr, indexer: &mut indexer, document_id };
+                value.serialize(indexer)?;
+            }
+
+            if reranked.contains(&attr) {
+                let number = value.serialize(ConvertToNumber)?;
+                ranked_map.insert((document_id, attr), number);
+            }
+
+            if refaceted.contains(&attr) {
+                for facet in value.serialize(ConvertToFacets)? {
+                    facet_map.insert(document_id, attr, facet);
+                }
+            }
+        }
+    }
+
+    let mut word_index = Lease::upgrade(&index.word_index());
+    let encoding = index.postings_encoding();
+
+    // the attributes must be removed from every segment, they are merged
+    if !unindexed.is_empty() {
+        let attributes = SetBuf::new_unchecked(unindexed);
+        let merged = merge_segments(&index.word_index_store(), word_index.segments())?;
+        let merged = merged.remove_attributes(&attributes);
+
+        batch.set_segment_postings(word_index.next_segment_id(), &merged, encoding);
+        word_index = Arc::new(word_index.replace(merged.map));
+    }
+
+    if !reindexed.is_empty() {
+        let delta_index = indexer.build();
+        let no_documents = SetBuf::new_unchecked(Vec::new());
+        let no_attributes = SetBuf::new_unchecked(Vec::new());
+
+        batch.set_segment_postings(word_index.next_segment_id(), &delta_index, encoding);
+        word_index = Arc::new(word_index.push(delta_index.map, &no_documents, no_attributes));
+    }
+
+    let mut schema_bytes = Vec::new();
+    schema.write_to_bin(&mut schema_bytes)?;
+    batch.set("schema", schema_bytes);
+
+    index.update(batch, word_index, Arc::new(ranked_map), Arc::new(facet_map))?;
+    index.schema.store(Arc::new(schema));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::{Database, SchemaAttr, FilterError, QueryBuilderExt};
+    use crate::database::tests::{simple_schema, reopen_index};
+    use crate::schema::{SchemaBuilder, STORED, INDEXED, RANKED};
+
+    #[test]
+    fn update_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+        let index = database.create_index("test".to_string(), simple_schema()).unwrap();
+
+        let mut addition = index.documents_addition();
+        addition.update_document(json!({ "id": 1, "title": "hello", "rank": 4 })).unwrap();
+        addition.finalize().unwrap();
+        let id = index.document_id("1").unwrap().unwrap();
+
+        // the title is not indexed anymore, the rank is not ranked anymore
+        // but is indexed and a new attribute is added in the middle
+        let mut builder = SchemaBuilder::with_identifier("id");
+        builder.new_attribute("id", STORED);
+        builder.new_attribute("year", STORED | RANKED);
+        builder.new_attribute("title", STORED);
+        builder.new_attribute("rank", STORED | INDEXED);
+        index.update_schema(builder.build()).unwrap();
+
+        let schema = index.schema();
+        assert_eq!(schema.attribute("title"), Some(SchemaAttr(1)));
+        assert_eq!(schema.attribute("rank"), Some(SchemaAttr(2)));
+        assert_eq!(schema.attribute("year"), Some(SchemaAttr(3)));
+
+        assert!(index.query_builder().query("hello", 0..10).unwrap().hits.is_empty());
+        assert_eq!(index.query_builder().query("4", 0..10).unwrap().hits.len(), 1);
+        assert!(index.ranked_map().is_empty());
+
+        let document: serde_json::Value = index.document(None, id).unwrap().unwrap();
+        assert_eq!(document, json!({ "id": 1, "title": "hello", "rank": 4 }));
+
+        // the schema is read back from the index tree
+        let index = reopen_index(&database, "test");
+        assert_eq!(index.schema().attribute("year"), Some(SchemaAttr(3)));
+        assert!(index.schema().props(SchemaAttr(3)).is_ranked());
+    }
+
+    #[test]
+    fn removed_attributes() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+        let index = database.create_index("test".to_string(), simple_schema()).unwrap();
+
+        let mut addition = index.documents_addition();
+        addition.update_document(json!({ "id": 1, "title": "hello", "rank": 4 })).unwrap();
+        addition.finalize().unwrap();
+
+        let mut builder = SchemaBuilder::with_identifier("id");
+        builder.new_attribute("id", STORED);
+        builder.new_attribute("title", STORED | INDEXED);
+        index.update_schema(builder.build()).unwrap();
+
+        let index = reopen_index(&database, "test");
+        assert_eq!(index.schema().attribute("rank"), None);
+        assert!(index.schema().iter().all(|(name, _, _)| name != "rank"));
+
+        match index.query_builder().with_filter_expr("rank > 1") {
+            Err(FilterError::UnknownAttribute { ref name, .. }) if name == "rank" => (),
+            result => panic!("unexpected result {:?}", result.map(drop)),
+        }
+
+        let document: serde_json::Value = index.document_by_key(None, "1").unwrap().unwrap();
+        assert_eq!(document, json!({ "id": 1, "title": "hello" }));
+
+        // an attribute added back keeps its number
+        index.update_schema(simple_schema()).unwrap();
+        assert_eq!(index.schema().attribute("rank"), Some(SchemaAttr(2)));
+    }
+}
@@ -0,0 +1,166 @@
+use std::fs;
+use std::time::SystemTime;
+
+use arc_swap::Lease;
+use hashbrown::HashMap;
+use meilidb_core::{DocumentId, Postings};
+
+use crate::SchemaAttr;
+use super::{Error, RawIndex, LAST_UPDATE_KEY};
+use super::{segment_key, postings_prefix, ranked_key, segment_words_path};
+
+/// Informations about the content of an index and the size of its stores.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexStats {
+    /// The number of documents of the index.
+    pub number_of_documents: u64,
+    /// The number of distinct words of the word index.
+    pub number_of_words: usize,
+    /// The number of postings of the word index, the removed ones are not counted.
+    pub number_of_postings: usize,
+    /// The number of documents which have a stored value for each attribute name.
+    pub fields_distribution: HashMap<String, usize>,
+    /// The size in bytes of the segments, postings and words files of the word index.
+    pub word_index_size: u64,
+    /// The size in bytes of the entries of the ranked map.
+    pub ranked_map_size: u64,
+    /// The last time the word index and the ranked map were written, if ever.
+    pub last_update: Option<SystemTime>,
+}
+
+/// Reads the word index, the ranked map and the stored documents keys
+/// to compute the statistics of the index.
+///
+/// The updates are only blocked while the word index, the ranked map and the schema are
+/// taken, the postings of this word index stay readable until it is dropped. The stored
+/// documents keys are read while the updates are applied, the fields distribution can
+/// therefore already count the documents of an update that is applied meanwhile.
+pub fn index_stats(index: &RawIndex) -> Result<IndexStats, Error> {
+    let (word_index, ranked_map, schema, number_of_documents) = {
+        let _lock = index.update_lock.lock().unwrap();
+        let word_index = Lease::upgrade(&index.word_index());
+        let ranked_map = Lease::upgrade(&index.ranked_map());
+        let schema = Lease::upgrade(&index.schema());
+        (word_index, ranked_map, schema, index.number_of_documents())
+    };
+
+    let mut number_of_postings = 0;
+    let mut word_index_size = 0;
+
+    for (si, segment) in word_index.segments().iter().enumerate() {
+        if let Some(bytes) = index.inner.get(segment_key(segment.id))? {
+            word_index_size += bytes.len() as u64;
+        }
+
+        let path = segment_words_path(&index.words_dir, segment.id);
+        if let Ok(metadata) = fs::metadata(&path) {
+            word_index_size += metadata.len();
+        }
+
+        let prefix = postings_prefix(segment.id);
+        for result in index.inner.range(prefix.clone()..) {
+            let (key, bytes) = result?;
+            if !key.starts_with(&prefix) { break }
+
+            word_index_size += bytes.len() as u64;
+
+            let postings = match Postings::from_bytes(bytes.to_vec()) {
+                Ok(postings) => postings,
+                Err(e) => {
+                    let word = String::from_utf8_lossy(&key[prefix.len()..]).into_owned();
+                    let message = e.to_string();
+                    return Err(Error::CorruptedPostings { segment: segment.id, word, message })
+                },
+            };
+
+            // postings removed by a more recent segment are outdated
+            let count = postings.iter().filter(|di| !word_index.is_removed(si, di)).count();
+            number_of_postings += count;
+        }
+    }
+
+    let names: Vec<_> = schema.iter().map(|(name, _, _)| name).collect();
+    let mut fields_distribution = HashMap::new();
+    for result in index.documents_fields() {
+        let (_, attr, _) = match result {
+            Ok(field) => field,
+            // the corrupted keys are reported by the verification
+            Err(Error::CorruptedDocumentKey(_)) => continue,
+            Err(e) => return Err(e),
+        };
+
+        // the attributes added after the schema was taken are not counted
+        if let Some(name) = names.get(attr.0 as usize) {
+            *fields_distribution.entry(name.to_string()).or_insert(0) += 1;
+        }
+    }
+
+    // every entry of the ranked map is stored under its own key
+    let key_size = ranked_key(DocumentId(0), SchemaAttr::min()).len();
+    let mut ranked_map_size = 0;
+    for number in ranked_map.values() {
+        ranked_map_size += key_size as u64 + bincode::serialized_size(number)?;
+    }
+
+    let last_update = match index.inner.get(LAST_UPDATE_KEY)? {
+        Some(bytes) => Some(bincode::deserialize(bytes.as_ref())?),
+        None => None,
+    };
+
+    Ok(IndexStats {
+        number_of_documents,
+        number_of_words: word_index.number_of_words(),
+        number_of_postings,
+        fields_distribution,
+        word_index_size,
+        ranked_map_size,
+        last_update,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use crate::Database;
+    use crate::database::tests::simple_schema;
+
+    #[test]
+    fn stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+        let index = database.create_index("test".to_string(), simple_schema()).unwrap();
+
+        let stats = index.stats().unwrap();
+        assert_eq!((stats.number_of_documents, stats.number_of_postings), (0, 0));
+        assert!(stats.fields_distribution.is_empty());
+        assert_eq!(stats.last_update, None);
+
+        let mut addition = index.documents_addition();
+        let document = json!({ "id": "abc", "title": "hello world", "rank": 1 });
+        addition.update_document(document).unwrap();
+        addition.update_document(json!({ "id": "def", "title": "hello" })).unwrap();
+        addition.finalize().unwrap();
+
+        let stats = index.stats().unwrap();
+        assert_eq!(stats.number_of_documents, 2);
+        assert_eq!(stats.number_of_words, 2);
+        assert_eq!(stats.number_of_postings, 3);
+        assert_eq!(stats.fields_distribution.get("id"), Some(&2));
+        assert_eq!(stats.fields_distribution.get("title"), Some(&2));
+        assert_eq!(stats.fields_distribution.get("rank"), Some(&1));
+        assert!(stats.word_index_size > 0);
+        assert!(stats.ranked_map_size > 0);
+        assert!(stats.last_update.is_some());
+
+        // the postings hidden by a more recent segment are not counted
+        let mut deletion = index.documents_deletion();
+        deletion.delete_document_by_key("abc").unwrap();
+        deletion.finalize().unwrap();
+
+        let stats = index.stats().unwrap();
+        assert_eq!(stats.number_of_documents, 1);
+        assert_eq!(stats.number_of_postings, 1);
+        assert_eq!(stats.fields_distribution.get("rank"), None);
+        assert_eq!(stats.ranked_map_size, 0);
+    }
+}
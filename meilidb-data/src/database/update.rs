@@ -0,0 +1,576 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use std::thread;
+
+use log::error;
+use meilidb_core::DocumentId;
+use serde::{Serialize, Deserialize};
+
+use super::{Error, RawIndex, DocumentsAddition, DocumentsDeletion};
+
+const LAST_UPDATE_ID_KEY: &str = "last-update-id";
+
+/// The delay after which the writer thread tries again to delete
+/// the postings of the removed segments that queries were reading.
+const GARBAGE_COLLECTION_INTERVAL: Duration = Duration::from_millis(500);
+
+fn update_key(update_id: u64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"update-");
+    bytes.extend_from_slice(&update_id.to_be_bytes()[..]);
+    bytes
+}
+
+fn result_key(update_id: u64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"result-");
+    bytes.extend_from_slice(&update_id.to_be_bytes()[..]);
+    bytes
+}
+
+fn extract_update_id(key: &[u8]) -> Option<u64> {
+    let bytes = key.get(b"update-".len()..)?;
+    if bytes.len() != 8 { return None }
+
+    let mut array = [0; 8];
+    array.copy_from_slice(bytes);
+    Some(u64::from_be_bytes(array))
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum Update {
+    DocumentsAddition(Vec<serde_json::Value>),
+    DocumentsPartialAddition(Vec<serde_json::Value>),
+    DocumentsDeletion(Vec<DocumentId>),
+}
+
+impl Update {
+    fn update_type(&self) -> UpdateType {
+        match self {
+            Update::DocumentsAddition(documents) => {
+                UpdateType::DocumentsAddition { number: documents.len() }
+            },
+            Update::DocumentsPartialAddition(documents) => {
+                UpdateType::DocumentsPartialAddition { number: documents.len() }
+            },
+            Update::DocumentsDeletion(documents) => {
+                UpdateType::DocumentsDeletion { number: documents.len() }
+            },
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UpdateType {
+    DocumentsAddition { number: usize },
+    DocumentsPartialAddition { number: usize },
+    DocumentsDeletion { number: usize },
+    /// The update could not be read, its type is not known.
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpdateResult {
+    pub update_id: u64,
+    pub update_type: UpdateType,
+    pub duration: Duration,
+    /// The number of tokens that were not indexed, they exceeded the
+    /// word limit of their attribute or their position was too large.
+    pub truncated_tokens: usize,
+    pub error: Option<String>,
+}
+
+/// Sent to the subscribers of an index each time an update has been processed,
+/// the update id is `None` for updates that have been applied without being enqueued.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpdateEvent {
+    pub update_id: Option<u64>,
+    pub update_type: UpdateType,
+    pub truncated_tokens: usize,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateStatus {
+    Enqueued,
+    Processing,
+    Processed(UpdateResult),
+    Failed(UpdateResult),
+}
+
+/// The persisted queue of updates of an index, updates are
+/// applied in order by a dedicated writer thread.
+pub struct Updates {
+    tree: Arc<sled::Tree>,
+    // the update currently processed by the writer thread,
+    // also guards the status transitions of the updates
+    processing: Mutex<Option<u64>>,
+    enqueue_lock: Mutex<()>,
+    notifier: Mutex<Sender<()>>,
+    subscribers: Mutex<Vec<Sender<UpdateEvent>>>,
+    closed: AtomicBool,
+    writer: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+impl Updates {
+    pub fn new(tree: Arc<sled::Tree>) -> (Updates, Receiver<()>) {
+        let (sender, receiver) = mpsc::channel();
+
+        let updates = Updates {
+            tree,
+            processing: Mutex::new(None),
+            enqueue_lock: Mutex::new(()),
+            notifier: Mutex::new(sender),
+            subscribers: Mutex::new(Vec::new()),
+            closed: AtomicBool::new(false),
+            writer: Mutex::new(None),
+        };
+
+        (updates, receiver)
+    }
+
+    pub fn enqueue(&self, update: &Update) -> Result<u64, Error> {
+        let bytes = serde_json::to_vec(update)?;
+
+        let update_id = {
+            let _lock = self.enqueue_lock.lock().unwrap();
+
+            let last_update_id = match self.tree.get(LAST_UPDATE_ID_KEY)? {
+                Some(bytes) => bincode::deserialize(bytes.as_ref())?,
+                None => 0u64,
+            };
+
+            // the counter is written before the update itself to never
+            // reuse the identifier of an update if a crash happens in between
+            let update_id = last_update_id + 1;
+            self.tree.insert(LAST_UPDATE_ID_KEY, bincode::serialize(&update_id)?)?;
+            self.tree.insert(update_key(update_id), bytes)?;
+
+            update_id
+        };
+
+        // the writer thread could have been stopped, the update
+        // will be processed the next time the index is opened
+        self.wake_writer();
+
+        Ok(update_id)
+    }
+
+    /// Wakes up the writer thread, to process the new updates or compact the word index.
+    pub fn wake_writer(&self) {
+        let _ = self.notifier.lock().unwrap().send(());
+    }
+
+    pub fn status(&self, update_id: u64) -> Result<Option<UpdateStatus>, Error> {
+        let processing = self.processing.lock().unwrap();
+
+        if let Some(bytes) = self.tree.get(result_key(update_id))? {
+            let result: UpdateResult = bincode::deserialize(bytes.as_ref())?;
+            let status = match result.error {
+                Some(_) => UpdateStatus::Failed(result),
+                None => UpdateStatus::Processed(result),
+            };
+            return Ok(Some(status))
+        }
+
+        if *processing == Some(update_id) {
+            return Ok(Some(UpdateStatus::Processing))
+        }
+
+        if self.tree.get(update_key(update_id))?.is_some() {
+            return Ok(Some(UpdateStatus::Enqueued))
+        }
+
+        Ok(None)
+    }
+
+    /// Removes the results of the updates up to the given one included,
+    /// returns the number of results removed.
+    pub fn clear_results(&self, up_to: u64) -> Result<usize, Error> {
+        let _processing = self.processing.lock().unwrap();
+
+        let start = result_key(u64::min_value());
+        let end = result_key(up_to);
+
+        let mut batch = self.tree.batch();
+        let mut count = 0;
+        for result in self.tree.range(start..=end) {
+            let (key, _) = result?;
+            batch.remove(key);
+            count += 1;
+        }
+        batch.apply()?;
+
+        Ok(count)
+    }
+
+    pub fn subscribe(&self) -> Receiver<UpdateEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    pub fn notify(&self, event: UpdateEvent) {
+        // subscribers that dropped their receiver are forgotten
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|s| s.send(event.clone()).is_ok());
+    }
+
+    /// Stops the writer thread once the update it is processing is done,
+    /// the remaining updates stay enqueued.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        let _ = self.notifier.lock().unwrap().send(());
+
+        if let Some(writer) = self.writer.lock().unwrap().take() {
+            // the writer thread could be the one closing the index
+            if writer.thread().id() != thread::current().id() {
+                let _ = writer.join();
+            }
+        }
+    }
+
+    /// Blocks the enqueuing of new updates and the recording of
+    /// the processed ones until the returned guard is dropped.
+    pub fn freeze(&self) -> FrozenUpdates {
+        let enqueue = self.enqueue_lock.lock().unwrap();
+        let processing = self.processing.lock().unwrap();
+        FrozenUpdates { _enqueue: enqueue, _processing: processing }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Returns the oldest enqueued update, an update that can not
+    /// be read is returned along with the reason why.
+    fn next_update(&self) -> Result<Option<(u64, Result<Update, Error>)>, Error> {
+        let start = update_key(u64::min_value());
+        let end = update_key(u64::max_value());
+
+        match self.tree.range(start..=end).next() {
+            Some(result) => {
+                let (key, bytes) = result?;
+                let update_id = match extract_update_id(&key) {
+                    Some(update_id) => update_id,
+                    None => return Ok(None),
+                };
+                let update = serde_json::from_slice(bytes.as_ref()).map_err(Error::from);
+                Ok(Some((update_id, update)))
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn start_processing(&self, update_id: u64) {
+        *self.processing.lock().unwrap() = Some(update_id);
+    }
+
+    fn end_processing(&self, result: &UpdateResult) -> Result<(), Error> {
+        let mut processing = self.processing.lock().unwrap();
+
+        let bytes = bincode::serialize(result)?;
+        let mut batch = self.tree.batch();
+        batch.insert(result_key(result.update_id), bytes);
+        batch.remove(update_key(result.update_id));
+        batch.apply()?;
+        *processing = None;
+
+        Ok(())
+    }
+}
+
+pub struct FrozenUpdates<'a> {
+    _enqueue: MutexGuard<'a, ()>,
+    _processing: MutexGuard<'a, Option<u64>>,
+}
+
+/// Applies an update, returns the number of tokens that were not indexed.
+fn apply_update(index: &RawIndex, update: Update) -> Result<usize, Error> {
+    match update {
+        Update::DocumentsAddition(documents) => {
+            let mut addition = DocumentsAddition::from_raw(index.clone());
+            for document in documents {
+                addition.update_document(document)?;
+            }
+            addition.commit()
+        },
+        Update::DocumentsPartialAddition(documents) => {
+            let mut addition = DocumentsAddition::partial_from_raw(index.clone());
+            for document in documents {
+                addition.update_document(document)?;
+            }
+            addition.commit()
+        },
+        Update::DocumentsDeletion(documents) => {
+            let mut deletion = DocumentsDeletion::from_raw(index.clone());
+            for id in documents {
+                deletion.delete_document(id);
+            }
+            deletion.commit()?;
+            Ok(0)
+        },
+    }
+}
+
+fn process_pending_updates(index: &RawIndex) -> Result<(), Error> {
+    while let Some((update_id, update)) = index.updates.next_update()? {
+        if index.updates.is_closed() { break }
+
+        index.updates.start_processing(update_id);
+
+        // an update that can not be read is recorded as failed
+        // instead of blocking the updates enqueued after it
+        let start = Instant::now();
+        let (update_type, result) = match update {
+            Ok(update) => (update.update_type(), apply_update(index, update)),
+            Err(e) => (UpdateType::Unknown, Err(e)),
+        };
+
+        let (truncated_tokens, error) = match result {
+            Ok(truncated_tokens) => (truncated_tokens, None),
+            Err(e) => (0, Some(e.to_string())),
+        };
+
+        let result = UpdateResult {
+            update_id,
+            update_type,
+            duration: start.elapsed(),
+            truncated_tokens,
+            error,
+        };
+
+        index.updates.end_processing(&result)?;
+
+        let event = UpdateEvent {
+            update_id: Some(result.update_id),
+            update_type: result.update_type,
+            truncated_tokens: result.truncated_tokens,
+            error: result.error,
+        };
+        index.updates.notify(event);
+    }
+
+    Ok(())
+}
+
+/// Spawns the writer thread of the index, it only keeps a weak handle on the index
+/// as the updates own the sender waking it up, it stops once the index is dropped.
+pub fn spawn_update_system(index: &RawIndex, receiver: Receiver<()>) {
+    let weak_index = index.downgrade();
+
+    let writer = thread::spawn(move || {
+        // updates can remain from a previous run, they are processed first
+        loop {
+            let index = match weak_index.upgrade() {
+                Some(index) => index,
+                None => break,
+            };
+
+            if index.updates.is_closed() { break }
+
+            if let Err(e) = process_pending_updates(&index) {
+                error!("error while processing updates; {}", e);
+            }
+
+            if index.updates.is_closed() { break }
+
+            if let Err(e) = index.compact_word_index() {
+                error!("error while compacting the word index; {}", e);
+            }
+
+            let remaining_garbage = match index.collect_garbage_segments() {
+                Ok(remaining) => remaining,
+                Err(e) => {
+                    error!("error while deleting the postings of the removed segments; {}", e);
+                    0
+                },
+            };
+
+            drop(index);
+
+            // the queries reading the removed segments do not notify their end
+            if remaining_garbage != 0 {
+                match receiver.recv_timeout(GARBAGE_COLLECTION_INTERVAL) {
+                    Ok(()) | Err(RecvTimeoutError::Timeout) => (),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            } else if receiver.recv().is_err() {
+                break
+            }
+        }
+    });
+
+    *index.updates.writer.lock().unwrap() = Some(writer);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::{Database, Index};
+    use crate::schema::{SchemaBuilder, STORED, INDEXED};
+
+    fn create_index(database: &Database) -> Index {
+        let mut builder = SchemaBuilder::with_identifier("id");
+        builder.new_attribute("id", STORED);
+        builder.new_attribute("title", STORED | INDEXED);
+        database.create_index("test".to_string(), builder.build()).unwrap()
+    }
+
+    #[test]
+    fn status_transitions() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+        let index = create_index(&database);
+        let events = index.subscribe();
+
+        // the writer thread can not apply the updates while the lock is held
+        let lock = index.0.update_lock.lock().unwrap();
+
+        let documents = vec![json!({ "id": 1, "title": "hello" })];
+        let first = index.enqueue_documents_addition(documents).unwrap();
+        while index.update_status(first).unwrap() != Some(UpdateStatus::Processing) {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        let second = index.enqueue_documents_addition(vec![json!({ "id": 2 })]).unwrap();
+        let third = index.enqueue_documents_addition(vec![json!({ "title": "no id" })]).unwrap();
+        let fourth = index.enqueue_documents_deletion(vec![DocumentId(0)]).unwrap();
+        assert_eq!(index.update_status(second).unwrap(), Some(UpdateStatus::Enqueued));
+
+        // a payload that can not be read must not block the next updates
+        index.0.updates.tree.insert(update_key(second), &b"not json"[..]).unwrap();
+        drop(lock);
+
+        for _ in 0..4 {
+            events.recv_timeout(Duration::from_secs(10)).unwrap();
+        }
+
+        match index.update_status(first).unwrap() {
+            Some(UpdateStatus::Processed(result)) => {
+                assert_eq!(result.update_type, UpdateType::DocumentsAddition { number: 1 });
+            },
+            status => panic!("unexpected status {:?}", status),
+        }
+
+        match index.update_status(second).unwrap() {
+            Some(UpdateStatus::Failed(result)) => {
+                assert_eq!(result.update_type, UpdateType::Unknown);
+                assert!(result.error.is_some());
+            },
+            status => panic!("unexpected status {:?}", status),
+        }
+
+        match index.update_status(third).unwrap() {
+            Some(UpdateStatus::Failed(result)) => assert!(result.error.is_some()),
+            status => panic!("unexpected status {:?}", status),
+        }
+
+        match index.update_status(fourth).unwrap() {
+            Some(UpdateStatus::Processed(result)) => {
+                assert_eq!(result.update_type, UpdateType::DocumentsDeletion { number: 1 });
+            },
+            status => panic!("unexpected status {:?}", status),
+        }
+
+        assert_eq!(index.update_status(fourth + 1).unwrap(), None);
+        assert_eq!(index.number_of_documents(), 1);
+
+        // the results are kept until they are cleared
+        assert_eq!(index.clear_update_results(second).unwrap(), 2);
+        assert_eq!(index.update_status(first).unwrap(), None);
+        assert_eq!(index.update_status(second).unwrap(), None);
+        assert!(index.update_status(third).unwrap().is_some());
+        assert_eq!(index.clear_update_results(second).unwrap(), 0);
+    }
+
+    #[test]
+    fn writer_stops_with_the_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+        let index = create_index(&database);
+
+        let writer = index.0.updates.writer.lock().unwrap().take().unwrap();
+
+        drop(index);
+        drop(database);
+
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn truncated_tokens() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+
+        let mut builder = SchemaBuilder::with_identifier("id");
+        builder.new_attribute("id", STORED);
+        builder.new_attribute("title", (STORED | INDEXED).with_word_limit(2));
+        let index = database.create_index("test".to_string(), builder.build()).unwrap();
+        let events = index.subscribe();
+
+        let mut addition = index.documents_addition();
+        addition.update_document(json!({ "id": 1, "title": "a long title" })).unwrap();
+        addition.finalize().unwrap();
+        assert_eq!(events.recv().unwrap().truncated_tokens, 1);
+
+        let documents = vec![json!({ "id": 2, "title": "an even longer title" })];
+        let update_id = index.enqueue_documents_addition(documents).unwrap();
+        let event = events.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!((event.update_id, event.truncated_tokens), (Some(update_id), 2));
+
+        match index.update_status(update_id).unwrap() {
+            Some(UpdateStatus::Processed(result)) => assert_eq!(result.truncated_tokens, 2),
+            status => panic!("unexpected status {:?}", status),
+        }
+    }
+
+    #[test]
+    fn update_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+
+        let mut builder = SchemaBuilder::with_inferred_identifier();
+        builder.new_attribute("id", STORED);
+        builder.new_attribute("book_id", STORED);
+        let index = database.create_index("test".to_string(), builder.build()).unwrap();
+        let events = index.subscribe();
+
+        // both additions infer a different identifier, the second one can not be committed
+        let mut first = index.documents_addition();
+        first.update_document(json!({ "id": 1 })).unwrap();
+        let mut second = index.documents_addition();
+        second.update_document(json!({ "book_id": 2 })).unwrap();
+
+        first.finalize().unwrap();
+        let event = events.recv().unwrap();
+        let update_type = UpdateType::DocumentsAddition { number: 1 };
+        let truncated_tokens = 0;
+        let expected = UpdateEvent { update_id: None, update_type, truncated_tokens, error: None };
+        assert_eq!(event, expected);
+
+        assert!(second.finalize().is_err());
+        let event = events.recv().unwrap();
+        assert_eq!(event.update_id, None);
+        assert_eq!(event.update_type, UpdateType::DocumentsAddition { number: 1 });
+        assert!(event.error.is_some());
+
+        let mut deletion = index.documents_deletion();
+        deletion.delete_document(DocumentId(0));
+        deletion.delete_document(DocumentId(0));
+        deletion.finalize().unwrap();
+        let event = events.recv().unwrap();
+        let update_type = UpdateType::DocumentsDeletion { number: 1 };
+        let truncated_tokens = 0;
+        let expected = UpdateEvent { update_id: None, update_type, truncated_tokens, error: None };
+        assert_eq!(event, expected);
+
+        let update_id = index.enqueue_documents_addition(vec![json!({ "id": 3 })]).unwrap();
+        let event = events.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(event.update_id, Some(update_id));
+        assert_eq!(event.error, None);
+    }
+}
@@ -0,0 +1,89 @@
+use meilidb_core::shared_data_cursor::FromSharedDataCursor;
+use meilidb_core::{DocumentId, Segment, Store, mmap_words};
+
+use super::{Error, RawIndex, segment_key, extract_segment_id, segment_words_path};
+
+/// The integrity problems found in the stores of an index.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// The segments of the word index that can not be read.
+    pub corrupted_segments: Vec<u64>,
+    /// The words of a segment whose postings are missing or can not be read.
+    pub corrupted_postings: Vec<(u64, String)>,
+    /// The keys of the stored documents that can not be read.
+    pub corrupted_document_keys: Vec<Vec<u8>>,
+    /// The documents with stored attribute values that can not be read.
+    pub corrupted_documents: Vec<DocumentId>,
+}
+
+impl IntegrityReport {
+    pub fn is_ok(&self) -> bool {
+        self.corrupted_segments.is_empty() &&
+        self.corrupted_postings.is_empty() &&
+        self.corrupted_document_keys.is_empty() &&
+        self.corrupted_documents.is_empty()
+    }
+}
+
+/// Reads every segment, posting and stored document of the index
+/// and reports the ones that are corrupted, updates are blocked meanwhile.
+pub fn verify_index(index: &RawIndex) -> Result<IntegrityReport, Error> {
+    let _lock = index.update_lock.lock().unwrap();
+    let mut report = IntegrityReport::default();
+
+    // the segments that could not be read when the index was opened
+    // to be repaired are replaced by empty ones, they are read again
+    let start = segment_key(u64::min_value());
+    let end = segment_key(u64::max_value());
+    for result in index.inner.range(start..=end) {
+        let (key, bytes) = result?;
+        let id = match extract_segment_id(&key) {
+            Some(id) => id,
+            None => continue,
+        };
+
+        let path = segment_words_path(&index.words_dir, id);
+        let words_corrupted = path.exists() && unsafe { mmap_words(&path) }.is_err();
+
+        if Segment::from_bytes(bytes.to_vec()).is_err() || words_corrupted {
+            report.corrupted_segments.push(id);
+        }
+    }
+
+    let store = index.word_index_store();
+    for segment in store.word_index().segments() {
+        let mut words = Vec::new();
+        segment.for_each_word(|word| words.push(word.to_vec()));
+
+        for word in words {
+            match store.word_indexes(segment.id, &word) {
+                Ok(Some(_)) => (),
+                Ok(None) | Err(Error::CorruptedPostings { .. }) => {
+                    let word = String::from_utf8_lossy(&word).into_owned();
+                    report.corrupted_postings.push((segment.id, word));
+                },
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    for result in index.documents_fields() {
+        let (document_id, _, value) = match result {
+            Ok(field) => field,
+            Err(Error::CorruptedDocumentKey(key)) => {
+                report.corrupted_document_keys.push(key);
+                continue
+            },
+            Err(e) => return Err(e),
+        };
+
+        if rmp_serde::from_slice::<serde_json::Value>(value.as_ref()).is_err() {
+            // the fields of a document are contiguous
+            if report.corrupted_documents.last() != Some(&document_id) {
+                report.corrupted_documents.push(document_id);
+            }
+        }
+    }
+
+    Ok(report)
+}
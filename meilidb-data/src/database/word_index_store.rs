@@ -0,0 +1,215 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use arc_swap::Lease;
+use log::error;
+use meilidb_core::{QueryBuilder, Filter, SearchResult};
+use meilidb_core::{DocumentId, Postings, SegmentedIndex as WordIndex, Store};
+
+use crate::{Schema, SchemaAttr, RankedMap, FacetMap, FacetCounts};
+use crate::filter::{FilterExpr, FilterError};
+use super::{Error, RawIndex, postings_key, document_key};
+
+/// Gives access to the segments of the word index,
+/// the postings of the words are lazily loaded from the index tree.
+pub struct WordIndexStore {
+    word_index: Arc<WordIndex>,
+    schema: Arc<Schema>,
+    ranked_map: Arc<RankedMap>,
+    facet_map: Arc<FacetMap>,
+    tree: Arc<sled::Tree>,
+}
+
+impl WordIndexStore {
+    pub(crate) fn from_raw(index: &RawIndex) -> WordIndexStore {
+        WordIndexStore {
+            word_index: Lease::upgrade(&index.word_index()),
+            schema: Lease::upgrade(&index.schema()),
+            ranked_map: Lease::upgrade(&index.ranked_map()),
+            facet_map: Lease::upgrade(&index.facet_map()),
+            tree: index.inner.clone(),
+        }
+    }
+}
+
+impl Store for WordIndexStore {
+    type Error = Error;
+
+    fn word_index(&self) -> &WordIndex {
+        &self.word_index
+    }
+
+    fn word_indexes(&self, segment: u64, word: &[u8]) -> Result<Option<Postings>, Error> {
+        let bytes = match self.tree.get(postings_key(segment, word))? {
+            Some(bytes) => bytes,
+            None => return Ok(None),
+        };
+
+        match Postings::from_bytes(bytes.to_vec()) {
+            Ok(postings) => Ok(Some(postings)),
+            Err(e) => {
+                let word = String::from_utf8_lossy(word).into_owned();
+                Err(Error::CorruptedPostings { segment, word, message: e.to_string() })
+            },
+        }
+    }
+}
+
+/// Evaluates a filter expression against the ranked map and the stored fields of the documents.
+///
+/// The ranked map is the one of the query builder creation but the stored fields are read
+/// from the tree at query time, an update applied in the meantime can be seen by the filter.
+pub struct DocumentsFilter {
+    expr: FilterExpr,
+    ranked_map: Arc<RankedMap>,
+    tree: Arc<sled::Tree>,
+}
+
+impl Filter for DocumentsFilter {
+    fn accept(&self, id: DocumentId) -> bool {
+        let ranked = |attr: SchemaAttr| self.ranked_map.get(&(id, attr)).cloned();
+        let stored = |attr: SchemaAttr| {
+            match self.tree.get(document_key(id, attr)) {
+                Ok(bytes) => rmp_serde::from_slice(bytes?.as_ref()).ok(),
+                Err(e) => {
+                    error!("error while reading the fields of the document {:?}; {}", id, e);
+                    None
+                },
+            }
+        };
+
+        self.expr.test(&ranked, &stored)
+    }
+}
+
+/// Adds the filter expressions to the query builders of the indexes.
+pub trait QueryBuilderExt<'c> {
+    /// Only returns the documents matching the filter expression,
+    /// e.g. `price < 100 AND (brand = "acme" OR in_stock = true)`.
+    ///
+    /// The attributes of the expression must be stored or ranked ones.
+    fn with_filter_expr(
+        self,
+        expr: &str,
+    ) -> Result<QueryBuilder<'c, WordIndexStore, DocumentsFilter>, FilterError>;
+
+    /// Returns the documents in the range along with the number of documents having
+    /// each value of the given faceted attributes, among all the documents matching
+    /// the query and the filter.
+    fn query_with_facets(
+        self,
+        query: &str,
+        range: Range<usize>,
+        facets: &[&str],
+    ) -> Result<(SearchResult, FacetCounts), Error>;
+}
+
+impl<'c, FI: Filter> QueryBuilderExt<'c> for QueryBuilder<'c, WordIndexStore, FI> {
+    fn with_filter_expr(
+        self,
+        expr: &str,
+    ) -> Result<QueryBuilder<'c, WordIndexStore, DocumentsFilter>, FilterError>
+    {
+        let store = self.store();
+        let filter = DocumentsFilter {
+            expr: FilterExpr::parse(&store.schema, expr)?,
+            ranked_map: store.ranked_map.clone(),
+            tree: store.tree.clone(),
+        };
+
+        Ok(self.with_custom_filter(filter))
+    }
+
+    fn query_with_facets(
+        self,
+        query: &str,
+        range: Range<usize>,
+        facets: &[&str],
+    ) -> Result<(SearchResult, FacetCounts), Error>
+    {
+        let store = self.store();
+        let schema = store.schema.clone();
+        let facet_map = store.facet_map.clone();
+
+        let mut attributes = Vec::with_capacity(facets.len());
+        for name in facets {
+            match schema.attribute(name) {
+                Some(attr) if schema.props(attr).is_faceted() => attributes.push((name, attr)),
+                _ => return Err(Error::AttributeNotFaceted(name.to_string())),
+            }
+        }
+
+        let (result, ids) = self.query_with_documents_ids(query, range)?;
+
+        let mut counts = FacetCounts::new();
+        for (name, attr) in attributes {
+            counts.insert(name.to_string(), facet_map.counts(attr, &ids));
+        }
+
+        Ok((result, counts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::Database;
+    use crate::database::tests::simple_schema;
+
+    #[test]
+    fn estimated_and_exhaustive_counts() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+        let index = database.create_index("test".to_string(), simple_schema()).unwrap();
+
+        let mut addition = index.documents_addition();
+        for i in 0..100 {
+            let document = json!({ "id": format!("id{}", i), "title": "hello", "rank": i });
+            addition.update_document(document).unwrap();
+        }
+        addition.finalize().unwrap();
+
+        let accepted = |id: DocumentId| id.0 % 2 == 0;
+        let expected = index.0.documents_ids().filter(|id| accepted(*id.as_ref().unwrap())).count();
+
+        // the filter is evaluated on every matching document by default
+        let result = index.query_builder().with_filter(accepted).query("hello", 0..10).unwrap();
+        assert!(result.exhaustive);
+        assert_eq!(result.total_hits, expected);
+
+        // otherwise it is only evaluated on the documents sorted
+        let mut builder = index.query_builder().with_filter(|_| true);
+        builder.set_exhaustive_count(false);
+        let result = builder.query("hello", 0..10).unwrap();
+        assert_eq!(result.hits.len(), 10);
+        assert!(!result.exhaustive);
+        assert_eq!(result.total_hits, 100);
+
+        // without any filter nor distinct rule every document is counted
+        let result = index.query_builder().query("hello", 0..10).unwrap();
+        assert!(result.exhaustive);
+        assert_eq!(result.total_hits, 100);
+    }
+
+    #[test]
+    fn excluded_words_are_exact() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = Database::start_default(dir.path()).unwrap();
+        let index = database.create_index("test".to_string(), simple_schema()).unwrap();
+
+        let mut addition = index.documents_addition();
+        addition.update_document(json!({ "id": "abc", "title": "jaguar formula" })).unwrap();
+        addition.update_document(json!({ "id": "def", "title": "jaguar formule" })).unwrap();
+        addition.update_document(json!({ "id": "ghi", "title": "jaguar formulas" })).unwrap();
+        addition.finalize().unwrap();
+
+        // the one typo and prefix neighbours of the excluded word are kept
+        let result = index.query_builder().query("jaguar -formula", 0..10).unwrap();
+        let mut ids: Vec<_> = result.hits.iter()
+            .map(|hit| index.external_id(hit.id).unwrap().unwrap())
+            .collect();
+        ids.sort();
+        assert_eq!(ids, vec!["def".to_string(), "ghi".to_string()]);
+    }
+}
@@ -0,0 +1,141 @@
+use hashbrown::HashMap;
+use meilidb_core::DocumentId;
+use sdset::duo::Intersection;
+use sdset::{Set, SetOperation};
+use serde::{Serialize, Deserialize};
+
+use crate::SchemaAttr;
+
+/// The values of the requested faceted attributes along with the number
+/// of matching documents having each of them, by attribute name.
+pub type FacetCounts = HashMap<String, Vec<(String, usize)>>;
+
+/// The ids of the documents having each value of the faceted attributes,
+/// the ids of a value are kept sorted.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FacetMap {
+    attributes: HashMap<SchemaAttr, HashMap<String, Vec<DocumentId>>>,
+}
+
+impl FacetMap {
+    pub fn insert(&mut self, id: DocumentId, attr: SchemaAttr, value: String) {
+        let values = self.attributes.entry(attr).or_insert_with(HashMap::new);
+        let ids = values.entry(value).or_insert_with(Vec::new);
+        if let Err(index) = ids.binary_search(&id) {
+            ids.insert(index, id);
+        }
+    }
+
+    pub fn remove_documents(&mut self, ids: &Set<DocumentId>) {
+        self.retain(|id, _| ids.binary_search(&id).is_err());
+    }
+
+    pub fn remove_documents_attributes(&mut self, pairs: &Set<(DocumentId, u16)>) {
+        self.retain(|id, attr| pairs.binary_search(&(id, attr.0)).is_err());
+    }
+
+    pub fn remove_attributes(&mut self, attrs: &[SchemaAttr]) {
+        self.attributes.retain(|attr, _| !attrs.contains(attr));
+    }
+
+    /// Returns `true` if the document has this value for the attribute.
+    pub fn contains(&self, id: DocumentId, attr: SchemaAttr, value: &str) -> bool {
+        self.attributes.get(&attr)
+            .and_then(|values| values.get(value))
+            .map_or(false, |ids| ids.binary_search(&id).is_ok())
+    }
+
+    /// Calls the function with every document, attribute and value of this map.
+    pub fn for_each<F>(&self, mut f: F)
+    where F: FnMut(DocumentId, SchemaAttr, &str),
+    {
+        for (attr, values) in &self.attributes {
+            for (value, ids) in values {
+                for id in ids {
+                    f(*id, *attr, value);
+                }
+            }
+        }
+    }
+
+    /// Moves all the values of the other map into this one.
+    pub fn extend(&mut self, other: FacetMap) {
+        for (attr, values) in other.attributes {
+            for (value, ids) in values {
+                for id in ids {
+                    self.insert(id, attr, value.clone());
+                }
+            }
+        }
+    }
+
+    /// Returns the values of the attribute along with the number of the given
+    /// documents having each of them, the most frequent values come first.
+    pub fn counts(&self, attr: SchemaAttr, documents: &Set<DocumentId>) -> Vec<(String, usize)> {
+        let values = match self.attributes.get(&attr) {
+            Some(values) => values,
+            None => return Vec::new(),
+        };
+
+        let mut counts = Vec::new();
+        let mut buffer: Vec<DocumentId> = Vec::new();
+        for (value, ids) in values {
+            buffer.clear();
+            let ids = Set::new_unchecked(ids.as_slice());
+            Intersection::new(ids, documents).extend_vec(&mut buffer);
+            if !buffer.is_empty() {
+                counts.push((value.clone(), buffer.len()));
+            }
+        }
+
+        counts.sort_unstable_by(|(av, ac), (bv, bc)| bc.cmp(ac).then_with(|| av.cmp(bv)));
+        counts
+    }
+
+    fn retain<F>(&mut self, f: F)
+    where F: Fn(DocumentId, SchemaAttr) -> bool,
+    {
+        for (attr, values) in &mut self.attributes {
+            for ids in values.values_mut() {
+                ids.retain(|id| f(*id, *attr));
+            }
+            values.retain(|_, ids| !ids.is_empty());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdset::SetBuf;
+
+    #[test]
+    fn counts() {
+        let brand = SchemaAttr(0);
+        let color = SchemaAttr(1);
+
+        let mut map = FacetMap::default();
+        map.insert(DocumentId(0), brand, "acme".to_owned());
+        map.insert(DocumentId(1), brand, "acme".to_owned());
+        map.insert(DocumentId(2), brand, "kiwi".to_owned());
+        map.insert(DocumentId(0), color, "red".to_owned());
+        map.insert(DocumentId(2), color, "red".to_owned());
+        map.insert(DocumentId(2), color, "blue".to_owned());
+
+        let documents = SetBuf::new_unchecked(vec![DocumentId(0), DocumentId(2)]);
+        let counts = map.counts(brand, &documents);
+        assert_eq!(counts, vec![("acme".to_owned(), 1), ("kiwi".to_owned(), 1)]);
+
+        let counts = map.counts(color, &documents);
+        assert_eq!(counts, vec![("red".to_owned(), 2), ("blue".to_owned(), 1)]);
+
+        let removed = SetBuf::new_unchecked(vec![(DocumentId(2), color.0)]);
+        map.remove_documents_attributes(&removed);
+        assert_eq!(map.counts(brand, &documents).len(), 2);
+        assert_eq!(map.counts(color, &documents), vec![("red".to_owned(), 1)]);
+
+        map.remove_documents(&SetBuf::new_unchecked(vec![DocumentId(0)]));
+        assert_eq!(map.counts(brand, &documents), vec![("kiwi".to_owned(), 1)]);
+        assert!(map.counts(color, &documents).is_empty());
+    }
+}
@@ -0,0 +1,577 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+use std::{error, fmt};
+
+use crate::{Number, Schema, SchemaAttr};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterError {
+    UnexpectedCharacter { position: usize, character: char },
+    UnterminatedString { position: usize },
+    UnexpectedToken { position: usize, found: String, expected: &'static str },
+    UnexpectedEnd { expected: &'static str },
+    UnknownAttribute { name: String, attributes: Vec<String> },
+    AttributeNotFilterable(String),
+    AttributeNotStored { name: String, value: &'static str },
+    InvalidComparison { name: String, operator: &'static str },
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::FilterError::*;
+        match self {
+            UnexpectedCharacter { position, character } => {
+                write!(f, "unexpected character {:?} at position {}", character, position)
+            },
+            UnterminatedString { position } => {
+                write!(f, "unterminated string starting at position {}", position)
+            },
+            UnexpectedToken { position, found, expected } => {
+                write!(f, "unexpected {} at position {}, expected {}", found, position, expected)
+            },
+            UnexpectedEnd { expected } => {
+                write!(f, "unexpected end of the filter, expected {}", expected)
+            },
+            UnknownAttribute { name, attributes } => {
+                write!(f, "unknown attribute {:?}, the schema attributes are ", name)?;
+                for (i, attribute) in attributes.iter().enumerate() {
+                    if i != 0 { f.write_str(", ")? }
+                    write!(f, "{:?}", attribute)?;
+                }
+                Ok(())
+            },
+            AttributeNotFilterable(name) => {
+                write!(f, "the attribute {:?} can not be filtered, \
+                           it must be stored or ranked", name)
+            },
+            AttributeNotStored { name, value } => {
+                write!(f, "the attribute {:?} can not be compared to a {}, \
+                           it is only ranked and must be stored", name, value)
+            },
+            InvalidComparison { name, operator } => {
+                write!(f, "the attribute {:?} can only be compared \
+                           to a number with the {} operator", name, operator)
+            },
+        }
+    }
+}
+
+impl error::Error for FilterError { }
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(Number),
+    String(String),
+    Bool(bool),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Operator {
+    Equal,
+    NotEqual,
+    Lower,
+    LowerOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Operator {
+    fn as_str(self) -> &'static str {
+        match self {
+            Operator::Equal => "=",
+            Operator::NotEqual => "!=",
+            Operator::Lower => "<",
+            Operator::LowerOrEqual => "<=",
+            Operator::Greater => ">",
+            Operator::GreaterOrEqual => ">=",
+        }
+    }
+
+    fn is_ordering(self) -> bool {
+        match self {
+            Operator::Equal | Operator::NotEqual => false,
+            _ => true,
+        }
+    }
+
+    /// Values that can not be compared are only accepted by the `!=` operator.
+    fn accepts(self, ordering: Option<Ordering>) -> bool {
+        let ordering = match ordering {
+            Some(ordering) => ordering,
+            None => return self == Operator::NotEqual,
+        };
+
+        match self {
+            Operator::Equal => ordering == Ordering::Equal,
+            Operator::NotEqual => ordering != Ordering::Equal,
+            Operator::Lower => ordering == Ordering::Less,
+            Operator::LowerOrEqual => ordering != Ordering::Greater,
+            Operator::Greater => ordering == Ordering::Greater,
+            Operator::GreaterOrEqual => ordering != Ordering::Less,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub attribute: SchemaAttr,
+    pub operator: Operator,
+    pub value: Value,
+}
+
+impl Condition {
+    fn test<R, S>(&self, ranked: &R, stored: &S) -> bool
+    where R: Fn(SchemaAttr) -> Option<Number>,
+          S: Fn(SchemaAttr) -> Option<serde_json::Value>,
+    {
+        // the ranked numbers are prefered, they do not need to be deserialized
+        if let Value::Number(number) = self.value {
+            if let Some(ranked) = ranked(self.attribute) {
+                return self.operator.accepts(compare_numbers(ranked, number))
+            }
+        }
+
+        match stored(self.attribute) {
+            Some(value) => self.matches(&value),
+            None => self.operator.accepts(None),
+        }
+    }
+
+    fn matches(&self, value: &serde_json::Value) -> bool {
+        use serde_json::Value as Json;
+
+        let ordering = match (value, &self.value) {
+            // an array matches if one of its values does
+            (Json::Array(values), _) => {
+                return if self.operator == Operator::NotEqual {
+                    values.iter().all(|v| self.matches(v))
+                } else {
+                    values.iter().any(|v| self.matches(v))
+                }
+            },
+            (Json::Number(number), Value::Number(other)) => {
+                let number = Number::from_str(&number.to_string()).ok();
+                number.and_then(|n| compare_numbers(n, *other))
+            },
+            (Json::String(string), Value::Number(other)) => {
+                let number = Number::from_str(string.trim()).ok();
+                number.and_then(|n| compare_numbers(n, *other))
+            },
+            (Json::String(string), Value::String(other)) => Some(string.as_str().cmp(other)),
+            (Json::Bool(boolean), Value::Bool(other)) => Some(boolean.cmp(other)),
+            _ => None,
+        };
+
+        self.operator.accepts(ordering)
+    }
+}
+
+fn compare_numbers(a: Number, b: Number) -> Option<Ordering> {
+    fn as_f64(number: Number) -> f64 {
+        match number {
+            Number::Unsigned(n) => n as f64,
+            Number::Signed(n) => n as f64,
+            Number::Float(n) => n.into_inner(),
+        }
+    }
+
+    match (a, b) {
+        (Number::Unsigned(a), Number::Unsigned(b)) => Some(a.cmp(&b)),
+        (Number::Signed(a), Number::Signed(b)) => Some(a.cmp(&b)),
+        (a, b) => as_f64(a).partial_cmp(&as_f64(b)),
+    }
+}
+
+/// A parsed filter expression, conditions on the attributes
+/// of the schema combined with the `AND`, `OR` and `NOT` operators.
+///
+/// ```text
+/// price < 100 AND (brand = "acme" OR in_stock = true)
+/// ```
+///
+/// The attributes must be stored or ranked, the ranked numbers are used when available,
+/// the attributes that are only ranked can only be compared to numbers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterExpr {
+    Condition(Condition),
+    And(Box<FilterExpr>, Box<FilterExpr>),
+    Or(Box<FilterExpr>, Box<FilterExpr>),
+    Not(Box<FilterExpr>),
+}
+
+impl FilterExpr {
+    pub fn parse(schema: &Schema, expr: &str) -> Result<FilterExpr, FilterError> {
+        let tokens = tokenize(expr)?;
+        let mut parser = Parser { schema, tokens: &tokens, position: 0 };
+
+        let filter = parser.parse_or()?;
+        match parser.peek() {
+            Some((position, token)) => Err(unexpected(*position, token, "AND, OR or the end")),
+            None => Ok(filter),
+        }
+    }
+
+    /// Returns whether a document matches this filter, given its
+    /// ranked numbers and its stored values by attribute.
+    pub fn test<R, S>(&self, ranked: &R, stored: &S) -> bool
+    where R: Fn(SchemaAttr) -> Option<Number>,
+          S: Fn(SchemaAttr) -> Option<serde_json::Value>,
+    {
+        match self {
+            FilterExpr::Condition(condition) => condition.test(ranked, stored),
+            FilterExpr::And(lhs, rhs) => lhs.test(ranked, stored) && rhs.test(ranked, stored),
+            FilterExpr::Or(lhs, rhs) => lhs.test(ranked, stored) || rhs.test(ranked, stored),
+            FilterExpr::Not(expr) => !expr.test(ranked, stored),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    String(String),
+    Number(Number),
+    Operator(Operator),
+    LeftParen,
+    RightParen,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Ident(ident) => write!(f, "{:?}", ident),
+            Token::String(string) => write!(f, "string {:?}", string),
+            Token::Number(Number::Unsigned(n)) => write!(f, "number {}", n),
+            Token::Number(Number::Signed(n)) => write!(f, "number {}", n),
+            Token::Number(Number::Float(n)) => write!(f, "number {}", n),
+            Token::Operator(operator) => write!(f, "operator {}", operator.as_str()),
+            Token::LeftParen => f.write_str("\"(\""),
+            Token::RightParen => f.write_str("\")\""),
+        }
+    }
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+fn tokenize(expr: &str) -> Result<Vec<(usize, Token)>, FilterError> {
+    let mut tokens = Vec::new();
+    let mut chars = expr.char_indices().peekable();
+
+    while let Some((position, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            '=' => Token::Operator(Operator::Equal),
+            '!' | '<' | '>' => {
+                let or_equal = chars.peek().map_or(false, |(_, c)| *c == '=');
+                if or_equal { chars.next(); }
+
+                let operator = match (c, or_equal) {
+                    ('!', true) => Operator::NotEqual,
+                    ('<', false) => Operator::Lower,
+                    ('<', true) => Operator::LowerOrEqual,
+                    ('>', false) => Operator::Greater,
+                    ('>', true) => Operator::GreaterOrEqual,
+                    _ => return Err(FilterError::UnexpectedCharacter { position, character: c }),
+                };
+
+                Token::Operator(operator)
+            },
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c)) => string.push(c),
+                            None => return Err(FilterError::UnterminatedString { position }),
+                        },
+                        Some((_, c)) => string.push(c),
+                        None => return Err(FilterError::UnterminatedString { position }),
+                    }
+                }
+                Token::String(string)
+            },
+            c if c.is_ascii_digit() || c == '-' => {
+                let mut end = position + c.len_utf8();
+                while let Some((i, c)) = chars.peek().cloned() {
+                    if !is_ident_char(c) && c != '+' { break }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+
+                match Number::from_str(&expr[position..end]) {
+                    Ok(number) => Token::Number(number),
+                    Err(_) => {
+                        let character = c;
+                        return Err(FilterError::UnexpectedCharacter { position, character })
+                    },
+                }
+            },
+            c if is_ident_char(c) => {
+                let mut end = position + c.len_utf8();
+                while let Some((i, c)) = chars.peek().cloned() {
+                    if !is_ident_char(c) { break }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                Token::Ident(expr[position..end].to_owned())
+            },
+            character => return Err(FilterError::UnexpectedCharacter { position, character }),
+        };
+
+        tokens.push((position, token));
+    }
+
+    Ok(tokens)
+}
+
+fn unexpected(position: usize, token: &Token, expected: &'static str) -> FilterError {
+    FilterError::UnexpectedToken { position, found: token.to_string(), expected }
+}
+
+fn is_keyword(token: &Token, keyword: &str) -> bool {
+    match token {
+        Token::Ident(ident) => ident.eq_ignore_ascii_case(keyword),
+        _ => false,
+    }
+}
+
+struct Parser<'a> {
+    schema: &'a Schema,
+    tokens: &'a [(usize, Token)],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a (usize, Token)> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self, expected: &'static str) -> Result<&'a (usize, Token), FilterError> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token.ok_or(FilterError::UnexpectedEnd { expected })
+    }
+
+    fn next_is_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek().map_or(false, |(_, token)| is_keyword(token, keyword));
+        if found { self.position += 1 }
+        found
+    }
+
+    fn parse_or(&mut self) -> Result<FilterExpr, FilterError> {
+        let mut lhs = self.parse_and()?;
+        while self.next_is_keyword("OR") {
+            let rhs = self.parse_and()?;
+            lhs = FilterExpr::Or(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_and(&mut self) -> Result<FilterExpr, FilterError> {
+        let mut lhs = self.parse_not()?;
+        while self.next_is_keyword("AND") {
+            let rhs = self.parse_not()?;
+            lhs = FilterExpr::And(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_not(&mut self) -> Result<FilterExpr, FilterError> {
+        if self.next_is_keyword("NOT") {
+            let expr = self.parse_not()?;
+            return Ok(FilterExpr::Not(Box::new(expr)))
+        }
+
+        match self.next("an attribute, NOT or \"(\"")? {
+            (_, Token::LeftParen) => {
+                let expr = self.parse_or()?;
+                match self.next("\")\"")? {
+                    (_, Token::RightParen) => Ok(expr),
+                    (position, token) => Err(unexpected(*position, token, "\")\"")),
+                }
+            },
+            (_, Token::Ident(name)) => self.parse_condition(name),
+            (position, token) => Err(unexpected(*position, token, "an attribute, NOT or \"(\"")),
+        }
+    }
+
+    fn parse_condition(&mut self, name: &str) -> Result<FilterExpr, FilterError> {
+        let attribute = match self.schema.attribute(name) {
+            Some(attribute) => attribute,
+            None => {
+                let attributes = self.schema.iter().map(|(n, _, _)| n.to_owned()).collect();
+                return Err(FilterError::UnknownAttribute { name: name.to_owned(), attributes })
+            },
+        };
+
+        let props = self.schema.props(attribute);
+        if !props.is_stored() && !props.is_ranked() {
+            return Err(FilterError::AttributeNotFilterable(name.to_owned()))
+        }
+
+        let operator = match self.next("an operator")? {
+            (_, Token::Operator(operator)) => *operator,
+            (position, token) => return Err(unexpected(*position, token, "an operator")),
+        };
+
+        let value = match self.next("a value")? {
+            (_, Token::Number(number)) => Value::Number(*number),
+            (_, Token::String(string)) => Value::String(string.clone()),
+            (_, token) if is_keyword(token, "true") => Value::Bool(true),
+            (_, token) if is_keyword(token, "false") => Value::Bool(false),
+            (position, token) => return Err(unexpected(*position, token, "a value")),
+        };
+
+        let is_number = match value { Value::Number(_) => true, _ => false };
+        if operator.is_ordering() && !is_number {
+            let name = name.to_owned();
+            return Err(FilterError::InvalidComparison { name, operator: operator.as_str() })
+        }
+
+        // only the numbers of the ranked attributes are known, the other values would never match
+        if !props.is_stored() {
+            let value = match value {
+                Value::Number(_) => None,
+                Value::String(_) => Some("string"),
+                Value::Bool(_) => Some("boolean"),
+            };
+
+            if let Some(value) = value {
+                return Err(FilterError::AttributeNotStored { name: name.to_owned(), value })
+            }
+        }
+
+        Ok(FilterExpr::Condition(Condition { attribute, operator, value }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{SchemaBuilder, STORED, INDEXED, RANKED};
+    use serde_json::json;
+
+    fn schema() -> Schema {
+        let mut builder = SchemaBuilder::with_identifier("id");
+        builder.new_attribute("id", STORED);
+        builder.new_attribute("price", STORED | RANKED);
+        builder.new_attribute("brand", STORED | INDEXED);
+        builder.new_attribute("in_stock", STORED);
+        builder.new_attribute("tags", STORED | INDEXED);
+        builder.new_attribute("description", INDEXED);
+        builder.build()
+    }
+
+    fn test(filter: &FilterExpr, document: serde_json::Value) -> bool {
+        let schema = schema();
+        let stored = |attr| document.get(schema.attribute_name(attr)).cloned();
+        filter.test(&|_| None, &stored)
+    }
+
+    #[test]
+    fn precedence() {
+        let schema = schema();
+        let filter = r#"price < 100 AND (brand = "acme" OR in_stock = true)"#;
+        let filter = FilterExpr::parse(&schema, filter).unwrap();
+
+        assert!(test(&filter, json!({ "price": 50, "brand": "acme", "in_stock": false })));
+        assert!(test(&filter, json!({ "price": 50, "brand": "kiwi", "in_stock": true })));
+        assert!(!test(&filter, json!({ "price": 50, "brand": "kiwi", "in_stock": false })));
+        assert!(!test(&filter, json!({ "price": 150, "brand": "acme", "in_stock": true })));
+
+        let filter = r#"NOT brand = "acme" or price >= 10.5 and in_stock = TRUE"#;
+        let filter = FilterExpr::parse(&schema, filter).unwrap();
+
+        assert!(test(&filter, json!({ "brand": "kiwi" })));
+        assert!(test(&filter, json!({ "brand": "acme", "price": 12, "in_stock": true })));
+        assert!(!test(&filter, json!({ "brand": "acme", "price": 12, "in_stock": false })));
+    }
+
+    #[test]
+    fn ranked_numbers() {
+        let schema = schema();
+        let filter = FilterExpr::parse(&schema, "price <= -2").unwrap();
+        let price = schema.attribute("price").unwrap();
+
+        let ranked = |attr: SchemaAttr| {
+            if attr == price { Some(Number::Signed(-3)) } else { None }
+        };
+        assert!(filter.test(&ranked, &|_| None));
+
+        let ranked = |attr: SchemaAttr| {
+            if attr == price { Some(Number::Unsigned(2)) } else { None }
+        };
+        assert!(!filter.test(&ranked, &|_| None));
+    }
+
+    #[test]
+    fn arrays_and_missing_values() {
+        let schema = schema();
+
+        let filter = FilterExpr::parse(&schema, r#"tags = "red""#).unwrap();
+        assert!(test(&filter, json!({ "tags": ["blue", "red"] })));
+        assert!(!test(&filter, json!({ "tags": ["blue"] })));
+        assert!(!test(&filter, json!({})));
+
+        let filter = FilterExpr::parse(&schema, r#"tags != "red""#).unwrap();
+        assert!(!test(&filter, json!({ "tags": ["blue", "red"] })));
+        assert!(test(&filter, json!({ "tags": ["blue"] })));
+        assert!(test(&filter, json!({})));
+    }
+
+    #[test]
+    fn errors() {
+        let schema = schema();
+
+        match FilterExpr::parse(&schema, "color = 1") {
+            Err(FilterError::UnknownAttribute { name, attributes }) => {
+                assert_eq!(name, "color");
+                assert_eq!(attributes.len(), 6);
+            },
+            otherwise => panic!("{:?}", otherwise),
+        }
+
+        let error = FilterExpr::parse(&schema, "description = 1").unwrap_err();
+        assert_eq!(error, FilterError::AttributeNotFilterable("description".to_owned()));
+
+        let error = FilterExpr::parse(&schema, r#"brand < "acme""#).unwrap_err();
+        let expected = FilterError::InvalidComparison { name: "brand".to_owned(), operator: "<" };
+        assert_eq!(error, expected);
+
+        match FilterExpr::parse(&schema, "price < 10 price") {
+            Err(FilterError::UnexpectedToken { position, .. }) => assert_eq!(position, 11),
+            otherwise => panic!("{:?}", otherwise),
+        }
+
+        let error = FilterExpr::parse(&schema, "(price < 10").unwrap_err();
+        assert_eq!(error, FilterError::UnexpectedEnd { expected: "\")\"" });
+
+        let error = FilterExpr::parse(&schema, r#"brand = "acme"#).unwrap_err();
+        assert_eq!(error, FilterError::UnterminatedString { position: 8 });
+
+        let error = FilterExpr::parse(&schema, "price ~ 10").unwrap_err();
+        assert_eq!(error, FilterError::UnexpectedCharacter { position: 6, character: '~' });
+
+        // a ranked attribute that is not stored only has numbers
+        let mut builder = SchemaBuilder::with_identifier("id");
+        builder.new_attribute("id", STORED);
+        builder.new_attribute("popularity", RANKED);
+        let schema = builder.build();
+
+        assert!(FilterExpr::parse(&schema, "popularity > 10").is_ok());
+        assert!(FilterExpr::parse(&schema, "popularity != 10").is_ok());
+
+        let error = FilterExpr::parse(&schema, r#"popularity = "high""#).unwrap_err();
+        let name = "popularity".to_owned();
+        assert_eq!(error, FilterError::AttributeNotStored { name, value: "string" });
+
+        let error = FilterExpr::parse(&schema, "NOT popularity = true").unwrap_err();
+        let name = "popularity".to_owned();
+        assert_eq!(error, FilterError::AttributeNotStored { name, value: "boolean" });
+    }
+}
@@ -1,57 +1,116 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryFrom;
 
 use deunicode::deunicode_with_tofu;
+use log::warn;
 use meilidb_core::{DocumentId, DocIndex};
 use meilidb_core::{Index as WordIndex, IndexBuilder as WordIndexBuilder};
 use meilidb_tokenizer::{is_cjk, Tokenizer, SeqTokenizer, Token};
 use sdset::Set;
 
-use crate::SchemaAttr;
+use crate::{Schema, SchemaAttr};
 
 type Word = Vec<u8>; // TODO make it be a SmallVec
 
+/// The maximum number of indexed words of an attribute
+/// when the schema does not specify one.
+pub const DEFAULT_WORD_LIMIT: usize = 1000;
+
 pub struct Indexer {
     word_limit: usize, // the maximum number of indexed words
+    attributes_word_limit: HashMap<SchemaAttr, usize>,
+    truncated_tokens: usize,
     indexed: BTreeMap<Word, Vec<DocIndex>>,
 }
 
 impl Indexer {
     pub fn new() -> Indexer {
-        Indexer {
-            word_limit: 1000,
-            indexed: BTreeMap::new(),
-        }
+        Indexer::with_word_limit(DEFAULT_WORD_LIMIT)
     }
 
     pub fn with_word_limit(limit: usize) -> Indexer {
         Indexer {
             word_limit: limit,
+            attributes_word_limit: HashMap::new(),
+            truncated_tokens: 0,
             indexed: BTreeMap::new(),
         }
     }
 
-    pub fn index_text(&mut self, id: DocumentId, attr: SchemaAttr, text: &str) {
-        for token in Tokenizer::new(text) {
-            let must_continue = index_token(token, id, attr, self.word_limit, &mut self.indexed);
-            if !must_continue { break }
+    /// Creates an indexer which respects the word limits of the schema attributes.
+    pub fn from_schema(schema: &Schema) -> Indexer {
+        let mut indexer = Indexer::new();
+        for (_, attr, props) in schema.iter() {
+            if let Some(limit) = props.word_limit() {
+                indexer.attributes_word_limit.insert(attr, limit);
+            }
         }
+        indexer
+    }
+
+    /// The number of tokens that were not indexed, either because they were
+    /// after the word limit of their attribute or because their position
+    /// or length could not be represented.
+    pub fn truncated_tokens(&self) -> usize {
+        self.truncated_tokens
+    }
+
+    pub fn index_text(&mut self, id: DocumentId, attr: SchemaAttr, text: &str) {
+        self.index_tokens(id, attr, Tokenizer::new(text))
     }
 
     pub fn index_text_seq<'a, I>(&mut self, id: DocumentId, attr: SchemaAttr, iter: I)
     where I: IntoIterator<Item=&'a str>,
     {
         let iter = iter.into_iter();
-        for token in SeqTokenizer::new(iter) {
-            let must_continue = index_token(token, id, attr, self.word_limit, &mut self.indexed);
-            if !must_continue { break }
+        self.index_tokens(id, attr, SeqTokenizer::new(iter))
+    }
+
+    fn index_tokens<'a, I>(&mut self, id: DocumentId, attr: SchemaAttr, mut tokens: I)
+    where I: Iterator<Item=Token<'a>>,
+    {
+        let word_limit = self.attributes_word_limit.get(&attr).cloned().unwrap_or(self.word_limit);
+
+        while let Some(token) = tokens.next() {
+            if token.word_index >= word_limit {
+                self.truncated_tokens += 1 + tokens.count();
+                break
+            }
+
+            if !index_token(token, id, attr, &mut self.indexed) {
+                self.truncated_tokens += 1;
+            }
+        }
+    }
+
+    /// Moves all the indexes of the other indexer into this one.
+    pub fn extend(&mut self, other: Indexer) {
+        self.truncated_tokens += other.truncated_tokens;
+        for (word, indexes) in other.indexed {
+            self.indexed.entry(word).or_insert_with(Vec::new).extend(indexes);
+        }
+    }
+
+    /// Only keeps the indexes for which the predicate returns `true`.
+    pub fn retain<F>(&mut self, f: F)
+    where F: Fn(&DocIndex) -> bool,
+    {
+        for indexes in self.indexed.values_mut() {
+            indexes.retain(|x| f(x));
         }
     }
 
     pub fn build(self) -> WordIndex {
+        if self.truncated_tokens != 0 {
+            warn!("{} tokens were not indexed, they exceeded the word limit \
+                   of their attribute or their position was too large", self.truncated_tokens);
+        }
+
         let mut builder = WordIndexBuilder::new();
 
         for (key, mut indexes) in self.indexed {
+            if indexes.is_empty() { continue }
+
             indexes.sort_unstable();
             indexes.dedup();
 
@@ -63,16 +122,14 @@ impl Indexer {
     }
 }
 
+/// Returns `false` if the token could not be indexed.
 fn index_token(
     token: Token,
     id: DocumentId,
     attr: SchemaAttr,
-    word_limit: usize,
     indexed: &mut BTreeMap<Word, Vec<DocIndex>>,
 ) -> bool
 {
-    if token.word_index >= word_limit { return false }
-
     let lower = token.word.to_lowercase();
     let token = Token { word: &lower, ..token };
     match token_to_docindex(id, attr, token) {
@@ -101,8 +158,8 @@ fn index_token(
 }
 
 fn token_to_docindex(id: DocumentId, attr: SchemaAttr, token: Token) -> Option<DocIndex> {
-    let word_index = u16::try_from(token.word_index).ok()?;
-    let char_index = u16::try_from(token.char_index).ok()?;
+    let word_index = u32::try_from(token.word_index).ok()?;
+    let char_index = u32::try_from(token.char_index).ok()?;
     let char_length = u16::try_from(token.word.chars().count()).ok()?;
 
     let docindex = DocIndex {
@@ -1,12 +1,18 @@
 mod database;
-mod index_event;
+mod facet_map;
+mod filter;
 mod indexer;
 mod number;
 mod ranked_map;
 mod serde;
 pub mod schema;
 
-pub use self::database::{Database, Index};
+pub use self::database::{Database, DatabaseOptions, Index, WordIndexStore};
+pub use self::database::{DocumentsFilter, QueryBuilderExt};
+pub use self::database::{DumpReport, IndexStats, IntegrityReport, ReindexProgress};
+pub use self::database::{UpdateStatus, UpdateResult, UpdateType};
+pub use self::facet_map::{FacetMap, FacetCounts};
+pub use self::filter::{FilterExpr, FilterError};
 pub use self::number::Number;
 pub use self::ranked_map::RankedMap;
 pub use self::schema::{Schema, SchemaAttr};
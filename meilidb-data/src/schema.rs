@@ -8,11 +8,20 @@ use std::sync::Arc;
 use serde::{Serialize, Deserialize};
 use linked_hash_map::LinkedHashMap;
 
-pub const STORED: SchemaProps  = SchemaProps { stored: true,  indexed: false, ranked: false };
-pub const INDEXED: SchemaProps = SchemaProps { stored: false, indexed: true,  ranked: false };
-pub const RANKED: SchemaProps  = SchemaProps { stored: false, indexed: false, ranked: true  };
-
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub const STORED: SchemaProps = SchemaProps {
+    stored: true, indexed: false, ranked: false, faceted: false, word_limit: None,
+};
+pub const INDEXED: SchemaProps = SchemaProps {
+    stored: false, indexed: true, ranked: false, faceted: false, word_limit: None,
+};
+pub const RANKED: SchemaProps = SchemaProps {
+    stored: false, indexed: false, ranked: true, faceted: false, word_limit: None,
+};
+pub const FACETED: SchemaProps = SchemaProps {
+    stored: false, indexed: false, ranked: false, faceted: true, word_limit: None,
+};
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct SchemaProps {
     #[serde(default)]
     stored: bool,
@@ -22,6 +31,12 @@ pub struct SchemaProps {
 
     #[serde(default)]
     ranked: bool,
+
+    #[serde(default)]
+    faceted: bool,
+
+    #[serde(default)]
+    word_limit: Option<usize>,
 }
 
 impl SchemaProps {
@@ -36,6 +51,20 @@ impl SchemaProps {
     pub fn is_ranked(self) -> bool {
         self.ranked
     }
+
+    pub fn is_faceted(self) -> bool {
+        self.faceted
+    }
+
+    /// Returns the maximum number of words of the attribute that are indexed,
+    /// `None` if the default limit of the indexer is used.
+    pub fn word_limit(self) -> Option<usize> {
+        self.word_limit
+    }
+
+    pub fn with_word_limit(self, limit: usize) -> SchemaProps {
+        SchemaProps { word_limit: Some(limit), ..self }
+    }
 }
 
 impl BitOr for SchemaProps {
@@ -46,20 +75,63 @@ impl BitOr for SchemaProps {
             stored: self.stored | other.stored,
             indexed: self.indexed | other.indexed,
             ranked: self.ranked | other.ranked,
+            faceted: self.faceted | other.faceted,
+            word_limit: self.word_limit.or(other.word_limit),
         }
     }
 }
 
+/// The binary schemas are prefixed by this marker and their version, the schemas
+/// written before start with the length of their identifier which can never be this big.
+const BIN_SCHEMA_MARKER: u64 = u64::max_value();
+const BIN_SCHEMA_VERSION: u32 = 1;
+
+/// The binary layout of the schemas written before their version was recorded.
 #[derive(Serialize, Deserialize)]
-pub struct SchemaBuilder {
+struct LegacySchemaBuilder {
     identifier: String,
+    attributes: LinkedHashMap<String, LegacySchemaProps>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LegacySchemaProps {
+    stored: bool,
+    indexed: bool,
+    ranked: bool,
+}
+
+impl From<LegacySchemaBuilder> for SchemaBuilder {
+    fn from(legacy: LegacySchemaBuilder) -> SchemaBuilder {
+        let mut builder = SchemaBuilder::with_identifier(legacy.identifier);
+        for (name, props) in legacy.attributes {
+            let LegacySchemaProps { stored, indexed, ranked } = props;
+            let props = SchemaProps { stored, indexed, ranked, ..SchemaProps::default() };
+            builder.new_attribute(name, props);
+        }
+        builder
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SchemaBuilder {
+    #[serde(default)]
+    identifier: Option<String>,
     attributes: LinkedHashMap<String, SchemaProps>,
 }
 
 impl SchemaBuilder {
     pub fn with_identifier<S: Into<String>>(name: S) -> SchemaBuilder {
         SchemaBuilder {
-            identifier: name.into(),
+            identifier: Some(name.into()),
+            attributes: LinkedHashMap::new(),
+        }
+    }
+
+    /// Creates a schema whose identifier will be inferred from
+    /// the first document added to the index.
+    pub fn with_inferred_identifier() -> SchemaBuilder {
+        SchemaBuilder {
+            identifier: None,
             attributes: LinkedHashMap::new(),
         }
     }
@@ -93,7 +165,7 @@ pub struct Schema {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct InnerSchema {
-    identifier: String,
+    identifier: Option<String>,
     attrs: HashMap<String, SchemaAttr>,
     props: Vec<(String, SchemaProps)>,
 }
@@ -134,16 +206,33 @@ impl Schema {
         Ok(())
     }
 
-    pub(crate) fn read_from_bin<R: Read>(reader: R) -> bincode::Result<Schema> {
-        let builder: SchemaBuilder = bincode::deserialize_from(reader)?;
+    /// Reads a schema written by `write_to_bin`, or by the versions
+    /// that did not record the version of the binary layout.
+    pub(crate) fn read_from_bin<R: Read>(mut reader: R) -> bincode::Result<Schema> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        let builder = match bincode::deserialize::<(u64, u32)>(&bytes) {
+            Ok((BIN_SCHEMA_MARKER, BIN_SCHEMA_VERSION)) => bincode::deserialize(&bytes[12..])?,
+            Ok((BIN_SCHEMA_MARKER, version)) => {
+                let message = format!("unsupported binary schema version {}", version);
+                return Err(Box::new(bincode::ErrorKind::Custom(message)))
+            },
+            _ => {
+                let legacy: LegacySchemaBuilder = bincode::deserialize(&bytes)?;
+                SchemaBuilder::from(legacy)
+            },
+        };
+
         Ok(builder.build())
     }
 
-    pub(crate) fn write_to_bin<W: Write>(&self, writer: W) -> bincode::Result<()> {
+    pub(crate) fn write_to_bin<W: Write>(&self, mut writer: W) -> bincode::Result<()> {
         let identifier = self.inner.identifier.clone();
         let attributes = self.attributes_ordered();
         let builder = SchemaBuilder { identifier, attributes };
 
+        bincode::serialize_into(&mut writer, &(BIN_SCHEMA_MARKER, BIN_SCHEMA_VERSION))?;
         bincode::serialize_into(writer, &builder)
     }
 
@@ -167,18 +256,70 @@ impl Schema {
         props
     }
 
+    /// Returns the identifier name, it is empty if it has not been inferred yet.
     pub fn identifier_name(&self) -> &str {
-        &self.inner.identifier
+        self.identifier().unwrap_or_default()
+    }
+
+    /// Returns the identifier name, `None` if it has not been inferred yet.
+    pub fn identifier(&self) -> Option<&str> {
+        self.inner.identifier.as_ref().map(String::as_str)
+    }
+
+    /// Returns the same schema but with the given identifier.
+    pub fn with_identifier<S: Into<String>>(&self, name: S) -> Schema {
+        let mut inner = InnerSchema::clone(&self.inner);
+        inner.identifier = Some(name.into());
+        Schema { inner: Arc::new(inner) }
     }
 
+    /// Returns the attribute with the given name, the removed attributes are not returned.
     pub fn attribute<S: AsRef<str>>(&self, name: S) -> Option<SchemaAttr> {
-        self.inner.attrs.get(name.as_ref()).cloned()
+        self.inner.attrs.get(name.as_ref()).cloned().filter(|attr| !self.is_removed(*attr))
+    }
+
+    /// An attribute without any property is one that has been removed by `evolve`,
+    /// it keeps its `SchemaAttr` but is not part of the schema anymore.
+    fn is_removed(&self, attr: SchemaAttr) -> bool {
+        self.props(attr) == SchemaProps::default()
     }
 
     pub fn attribute_name(&self, attr: SchemaAttr) -> &str {
         let (name, _) = &self.inner.props[attr.0 as usize];
         name
     }
+
+    /// Iterates over the attributes of the schema, the removed attributes are skipped.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item=(&'a str, SchemaAttr, SchemaProps)> + 'a {
+        self.inner.props.iter().enumerate()
+            .map(|(i, (name, props))| (name.as_str(), SchemaAttr(i as u16), *props))
+            .filter(move |(_, attr, _)| !self.is_removed(*attr))
+    }
+
+    /// Returns the schema with the attributes and properties of the new one
+    /// but where the existing attributes keep their `SchemaAttr`, the identifier
+    /// is kept if the new schema still needs to infer it.
+    ///
+    /// Attributes that are not part of the new schema anymore are kept without any
+    /// property and are considered removed, they get their `SchemaAttr` back if they
+    /// are added again. The new ones are numbered after the existing attributes.
+    pub fn evolve(&self, new: &Schema) -> Schema {
+        let identifier = new.inner.identifier.clone().or_else(|| self.inner.identifier.clone());
+        let mut builder = SchemaBuilder { identifier, attributes: LinkedHashMap::new() };
+
+        for (name, _) in &self.inner.props {
+            let props = new.attribute(name).map(|attr| new.props(attr)).unwrap_or_default();
+            builder.new_attribute(name.as_str(), props);
+        }
+
+        for (name, _, props) in new.iter() {
+            if !self.inner.attrs.contains_key(name) {
+                builder.new_attribute(name, props);
+            }
+        }
+
+        builder.build()
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -218,6 +359,26 @@ mod tests {
     use super::*;
     use std::error::Error;
 
+    #[test]
+    fn deserialize_legacy_bin() -> bincode::Result<()> {
+        let mut attributes = LinkedHashMap::new();
+        let props = LegacySchemaProps { stored: true, indexed: false, ranked: false };
+        attributes.insert("id".to_string(), props);
+        let props = LegacySchemaProps { stored: true, indexed: true, ranked: true };
+        attributes.insert("title".to_string(), props);
+
+        let legacy = LegacySchemaBuilder { identifier: "id".to_string(), attributes };
+        let bytes = bincode::serialize(&legacy)?;
+        let schema = Schema::read_from_bin(bytes.as_slice())?;
+
+        let mut builder = SchemaBuilder::with_identifier("id");
+        builder.new_attribute("id", STORED);
+        builder.new_attribute("title", STORED | INDEXED | RANKED);
+        assert_eq!(schema, builder.build());
+
+        Ok(())
+    }
+
     #[test]
     fn serialize_deserialize() -> bincode::Result<()> {
         let mut builder = SchemaBuilder::with_identifier("id");
@@ -236,6 +397,48 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn evolve_keep_attributes_numbers() {
+        let mut builder = SchemaBuilder::with_identifier("id");
+        builder.new_attribute("alpha", STORED);
+        builder.new_attribute("beta", STORED | INDEXED);
+        builder.new_attribute("gamma", INDEXED);
+        let schema = builder.build();
+
+        let mut builder = SchemaBuilder::with_identifier("id");
+        builder.new_attribute("delta", STORED);
+        builder.new_attribute("gamma", STORED | INDEXED);
+        builder.new_attribute("alpha", STORED | RANKED);
+        let new_schema = builder.build();
+
+        let schema = schema.evolve(&new_schema);
+
+        assert_eq!(schema.attribute("alpha"), Some(SchemaAttr(0)));
+        assert_eq!(schema.attribute("beta"), None);
+        assert_eq!(schema.attribute("gamma"), Some(SchemaAttr(2)));
+        assert_eq!(schema.attribute("delta"), Some(SchemaAttr(3)));
+
+        assert_eq!(schema.props(SchemaAttr(0)), STORED | RANKED);
+        assert_eq!(schema.props(SchemaAttr(1)), SchemaProps::default());
+        assert_eq!(schema.props(SchemaAttr(2)), STORED | INDEXED);
+        assert_eq!(schema.props(SchemaAttr(3)), STORED);
+
+        let names: Vec<_> = schema.iter().map(|(name, _, _)| name).collect();
+        assert_eq!(names, vec!["alpha", "gamma", "delta"]);
+
+        // the removed attribute is kept in the binary schema and gets its number back
+        let mut buffer = Vec::new();
+        schema.write_to_bin(&mut buffer).unwrap();
+        let schema = Schema::read_from_bin(buffer.as_slice()).unwrap();
+        assert_eq!(schema.attribute("beta"), None);
+
+        let mut builder = SchemaBuilder::with_identifier("id");
+        builder.new_attribute("beta", INDEXED);
+        let schema = schema.evolve(&builder.build());
+        assert_eq!(schema.attribute("beta"), Some(SchemaAttr(1)));
+        assert_eq!(schema.attribute("alpha"), None);
+    }
+
     #[test]
     fn serialize_deserialize_toml() -> Result<(), Box<Error>> {
         let mut builder = SchemaBuilder::with_identifier("id");
@@ -269,6 +472,67 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn word_limit() -> Result<(), Box<Error>> {
+        let mut builder = SchemaBuilder::with_identifier("id");
+        builder.new_attribute("alpha", STORED | INDEXED.with_word_limit(5000));
+        builder.new_attribute("beta", STORED | INDEXED);
+        let schema = builder.build();
+
+        assert_eq!(schema.props(SchemaAttr(0)).word_limit(), Some(5000));
+        assert_eq!(schema.props(SchemaAttr(1)).word_limit(), None);
+
+        let mut buffer = Vec::new();
+        schema.write_to_bin(&mut buffer)?;
+        let schema2 = Schema::read_from_bin(buffer.as_slice())?;
+        assert_eq!(schema, schema2);
+
+        let data = r#"
+            identifier = "id"
+
+            [attributes."alpha"]
+            stored = true
+            indexed = true
+            word_limit = 5000
+
+            [attributes."beta"]
+            stored = true
+            indexed = true
+        "#;
+        let schema2 = Schema::from_toml(data.as_bytes())?;
+        assert_eq!(schema, schema2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn inferred_identifier() -> Result<(), Box<Error>> {
+        let mut builder = SchemaBuilder::with_inferred_identifier();
+        builder.new_attribute("alpha", STORED);
+        let schema = builder.build();
+        assert_eq!(schema.identifier(), None);
+        assert_eq!(schema.identifier_name(), "");
+
+        let mut buffer = Vec::new();
+        schema.write_to_bin(&mut buffer)?;
+        let schema2 = Schema::read_from_bin(buffer.as_slice())?;
+        assert_eq!(schema, schema2);
+
+        let data = r#"
+            [attributes."alpha"]
+            stored = true
+        "#;
+        let schema2 = Schema::from_toml(data.as_bytes())?;
+        assert_eq!(schema, schema2);
+
+        let schema = schema.with_identifier("alpha_id");
+        assert_eq!(schema.identifier(), Some("alpha_id"));
+        assert_eq!(schema.identifier_name(), "alpha_id");
+        assert_eq!(schema.attribute("alpha"), Some(SchemaAttr(0)));
+
+        Ok(())
+    }
+
     #[test]
     fn serialize_deserialize_json() -> Result<(), Box<Error>> {
         let mut builder = SchemaBuilder::with_identifier("id");
@@ -45,9 +45,10 @@ impl<'de, 'a, 'b> de::Deserializer<'de> for &'b mut Deserializer<'a>
                 },
             }
         });
+        let schema = self.raw_index.schema();
         let iter = document_attributes.filter_map(|(_, attr, value)| {
             if self.fields.map_or(true, |f| f.contains(&attr)) {
-                let attribute_name = self.raw_index.schema().attribute_name(attr);
+                let attribute_name = schema.attribute_name(attr);
                 Some((attribute_name, Value::new(value)))
             } else {
                 None
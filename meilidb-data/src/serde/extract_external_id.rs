@@ -1,41 +1,65 @@
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
-
 use meilidb_core::DocumentId;
 use serde::Serialize;
 use serde::ser;
 
 use super::{SerializerError, ConvertToString};
 
-pub fn extract_document_id<D>(
+/// Returns the external id of the document, the string or integer value of its
+/// identifier attribute converted into a string, `1` and `"1"` are the same id.
+pub fn extract_external_id<D>(
     identifier: &str,
     document: &D,
-) -> Result<Option<DocumentId>, SerializerError>
+) -> Result<Option<String>, SerializerError>
 where D: serde::Serialize,
 {
-    let serializer = ExtractDocumentId { identifier };
+    let serializer = ExtractExternalId { identifier };
     document.serialize(serializer)
 }
 
-fn calculate_hash<T: Hash>(t: &T) -> u64 {
-    let mut s = DefaultHasher::new();
-    t.hash(&mut s);
-    s.finish()
+/// Computes the internal id of a document from its external id.
+///
+/// The 64 bits FNV-1a hash is used as it is stable across Rust releases,
+/// collisions are detected when documents are added.
+pub fn compute_document_id(external_id: &str) -> DocumentId {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in external_id.as_bytes() {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    DocumentId(hash)
+}
+
+/// Only strings and integers can be used as external ids.
+pub fn value_to_external_id<T: ?Sized + Serialize>(value: &T) -> Result<String, SerializerError> {
+    use serde_json::Value;
+
+    let value = serde_json::to_value(value).map_err(|e| SerializerError::Custom(e.to_string()))?;
+    let type_name = match value {
+        Value::String(string) => return Ok(string),
+        Value::Number(ref number) if !number.is_f64() => return Ok(number.to_string()),
+        Value::Number(_) => "float",
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Array(_) => "sequence",
+        Value::Object(_) => "map",
+    };
+
+    Err(SerializerError::InvalidDocumentIdType { type_name })
 }
 
-struct ExtractDocumentId<'a> {
+struct ExtractExternalId<'a> {
     identifier: &'a str,
 }
 
-impl<'a> ser::Serializer for ExtractDocumentId<'a> {
-    type Ok = Option<DocumentId>;
+impl<'a> ser::Serializer for ExtractExternalId<'a> {
+    type Ok = Option<String>;
     type Error = SerializerError;
     type SerializeSeq = ser::Impossible<Self::Ok, Self::Error>;
     type SerializeTuple = ser::Impossible<Self::Ok, Self::Error>;
     type SerializeTupleStruct = ser::Impossible<Self::Ok, Self::Error>;
     type SerializeTupleVariant = ser::Impossible<Self::Ok, Self::Error>;
-    type SerializeMap = ExtractDocumentIdMapSerializer<'a>;
-    type SerializeStruct = ExtractDocumentIdStructSerializer<'a>;
+    type SerializeMap = ExtractExternalIdMapSerializer<'a>;
+    type SerializeStruct = ExtractExternalIdStructSerializer<'a>;
     type SerializeStructVariant = ser::Impossible<Self::Ok, Self::Error>;
 
     forward_to_unserializable_type! {
@@ -143,9 +167,9 @@ impl<'a> ser::Serializer for ExtractDocumentId<'a> {
     }
 
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
-        let serializer = ExtractDocumentIdMapSerializer {
+        let serializer = ExtractExternalIdMapSerializer {
             identifier: self.identifier,
-            document_id: None,
+            external_id: None,
             current_key_name: None,
         };
 
@@ -158,9 +182,9 @@ impl<'a> ser::Serializer for ExtractDocumentId<'a> {
         _len: usize
     ) -> Result<Self::SerializeStruct, Self::Error>
     {
-        let serializer = ExtractDocumentIdStructSerializer {
+        let serializer = ExtractExternalIdStructSerializer {
             identifier: self.identifier,
-            document_id: None,
+            external_id: None,
         };
 
         Ok(serializer)
@@ -178,14 +202,14 @@ impl<'a> ser::Serializer for ExtractDocumentId<'a> {
     }
 }
 
-pub struct ExtractDocumentIdMapSerializer<'a> {
+pub struct ExtractExternalIdMapSerializer<'a> {
     identifier: &'a str,
-    document_id: Option<DocumentId>,
+    external_id: Option<String>,
     current_key_name: Option<String>,
 }
 
-impl<'a> ser::SerializeMap for ExtractDocumentIdMapSerializer<'a> {
-    type Ok = Option<DocumentId>;
+impl<'a> ser::SerializeMap for ExtractExternalIdMapSerializer<'a> {
+    type Ok = Option<String>;
     type Error = SerializerError;
 
     fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<(), Self::Error>
@@ -214,26 +238,25 @@ impl<'a> ser::SerializeMap for ExtractDocumentIdMapSerializer<'a> {
 
         if self.identifier == key {
             // TODO is it possible to have multiple ids?
-            let id = bincode::serialize(value).unwrap();
-            let hash = calculate_hash(&id);
-            self.document_id = Some(DocumentId(hash));
+            let id = value_to_external_id(value)?;
+            self.external_id = Some(id);
         }
 
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        Ok(self.document_id)
+        Ok(self.external_id)
     }
 }
 
-pub struct ExtractDocumentIdStructSerializer<'a> {
+pub struct ExtractExternalIdStructSerializer<'a> {
     identifier: &'a str,
-    document_id: Option<DocumentId>,
+    external_id: Option<String>,
 }
 
-impl<'a> ser::SerializeStruct for ExtractDocumentIdStructSerializer<'a> {
-    type Ok = Option<DocumentId>;
+impl<'a> ser::SerializeStruct for ExtractExternalIdStructSerializer<'a> {
+    type Ok = Option<String>;
     type Error = SerializerError;
 
     fn serialize_field<T: ?Sized>(
@@ -245,15 +268,14 @@ impl<'a> ser::SerializeStruct for ExtractDocumentIdStructSerializer<'a> {
     {
         if self.identifier == key {
             // TODO can it be possible to have multiple ids?
-            let id = bincode::serialize(value).unwrap();
-            let hash = calculate_hash(&id);
-            self.document_id = Some(DocumentId(hash));
+            let id = value_to_external_id(value)?;
+            self.external_id = Some(id);
         }
 
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
-        Ok(self.document_id)
+        Ok(self.external_id)
     }
 }
@@ -8,17 +8,19 @@ macro_rules! forward_to_unserializable_type {
     }
 }
 
+mod convert_to_facets;
 mod convert_to_number;
 mod convert_to_string;
 mod deserializer;
-mod extract_document_id;
+mod extract_external_id;
 mod indexer;
 mod serializer;
 
 pub use self::deserializer::Deserializer;
-pub use self::extract_document_id::extract_document_id;
+pub use self::extract_external_id::{extract_external_id, compute_document_id, value_to_external_id};
 pub use self::convert_to_string::ConvertToString;
 pub use self::convert_to_number::ConvertToNumber;
+pub use self::convert_to_facets::ConvertToFacets;
 pub use self::indexer::Indexer;
 pub use self::serializer::Serializer;
 
@@ -30,12 +32,14 @@ use crate::number::ParseNumberError;
 #[derive(Debug)]
 pub enum SerializerError {
     DocumentIdNotFound,
+    InvalidDocumentIdType { type_name: &'static str },
     RmpError(RmpError),
     SledError(sled::Error),
     ParseNumberError(ParseNumberError),
     UnserializableType { type_name: &'static str },
     UnindexableType { type_name: &'static str },
     UnrankableType { type_name: &'static str },
+    UnfacetableType { type_name: &'static str },
     Custom(String),
 }
 
@@ -51,6 +55,9 @@ impl fmt::Display for SerializerError {
             SerializerError::DocumentIdNotFound => {
                 write!(f, "serialized document does not have an id according to the schema")
             }
+            SerializerError::InvalidDocumentIdType { type_name } => {
+                write!(f, "{} are not valid document ids, only strings and integers are", type_name)
+            },
             SerializerError::RmpError(e) => write!(f, "rmp serde related error: {}", e),
             SerializerError::SledError(e) => write!(f, "sled related error: {}", e),
             SerializerError::ParseNumberError(e) => {
@@ -65,6 +72,9 @@ impl fmt::Display for SerializerError {
             SerializerError::UnrankableType { type_name } => {
                 write!(f, "{} types can not be used for ranking", type_name)
             },
+            SerializerError::UnfacetableType { type_name } => {
+                write!(f, "{} types can not be used as facets", type_name)
+            },
             SerializerError::Custom(s) => f.write_str(s),
         }
     }
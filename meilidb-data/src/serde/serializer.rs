@@ -1,17 +1,20 @@
 use meilidb_core::DocumentId;
 use serde::ser;
 
-use crate::database::RawIndex;
+use crate::database::WriteBatch;
+use crate::facet_map::FacetMap;
 use crate::ranked_map::RankedMap;
 use crate::indexer::Indexer as RawIndexer;
 use crate::schema::{Schema, SchemaAttr};
-use super::{SerializerError, ConvertToString, ConvertToNumber, Indexer};
+use super::{SerializerError, ConvertToString, ConvertToNumber, ConvertToFacets, Indexer};
 
 pub struct Serializer<'a> {
     pub schema: &'a Schema,
-    pub index: &'a RawIndex,
+    pub batch: &'a mut WriteBatch,
     pub indexer: &'a mut RawIndexer,
     pub ranked_map: &'a mut RankedMap,
+    pub facet_map: &'a mut FacetMap,
+    pub attributes: &'a mut Vec<SchemaAttr>,
     pub document_id: DocumentId,
 }
 
@@ -134,9 +137,11 @@ impl<'a> ser::Serializer for Serializer<'a> {
         Ok(MapSerializer {
             schema: self.schema,
             document_id: self.document_id,
-            index: self.index,
+            batch: self.batch,
             indexer: self.indexer,
             ranked_map: self.ranked_map,
+            facet_map: self.facet_map,
+            attributes: self.attributes,
             current_key_name: None,
         })
     }
@@ -150,9 +155,11 @@ impl<'a> ser::Serializer for Serializer<'a> {
         Ok(StructSerializer {
             schema: self.schema,
             document_id: self.document_id,
-            index: self.index,
+            batch: self.batch,
             indexer: self.indexer,
             ranked_map: self.ranked_map,
+            facet_map: self.facet_map,
+            attributes: self.attributes,
         })
     }
 
@@ -171,9 +178,11 @@ impl<'a> ser::Serializer for Serializer<'a> {
 pub struct MapSerializer<'a> {
     schema: &'a Schema,
     document_id: DocumentId,
-    index: &'a RawIndex,
+    batch: &'a mut WriteBatch,
     indexer: &'a mut RawIndexer,
     ranked_map: &'a mut RankedMap,
+    facet_map: &'a mut FacetMap,
+    attributes: &'a mut Vec<SchemaAttr>,
     current_key_name: Option<String>,
 }
 
@@ -208,9 +217,11 @@ impl<'a> ser::SerializeMap for MapSerializer<'a> {
         serialize_value(
             self.schema,
             self.document_id,
-            self.index,
+            self.batch,
             self.indexer,
             self.ranked_map,
+            self.facet_map,
+            self.attributes,
             &key,
             value,
         )
@@ -224,9 +235,11 @@ impl<'a> ser::SerializeMap for MapSerializer<'a> {
 pub struct StructSerializer<'a> {
     schema: &'a Schema,
     document_id: DocumentId,
-    index: &'a RawIndex,
+    batch: &'a mut WriteBatch,
     indexer: &'a mut RawIndexer,
     ranked_map: &'a mut RankedMap,
+    facet_map: &'a mut FacetMap,
+    attributes: &'a mut Vec<SchemaAttr>,
 }
 
 impl<'a> ser::SerializeStruct for StructSerializer<'a> {
@@ -243,9 +256,11 @@ impl<'a> ser::SerializeStruct for StructSerializer<'a> {
         serialize_value(
             self.schema,
             self.document_id,
-            self.index,
+            self.batch,
             self.indexer,
             self.ranked_map,
+            self.facet_map,
+            self.attributes,
             key,
             value,
         )
@@ -259,9 +274,11 @@ impl<'a> ser::SerializeStruct for StructSerializer<'a> {
 fn serialize_value<T: ?Sized>(
     schema: &Schema,
     document_id: DocumentId,
-    index: &RawIndex,
+    batch: &mut WriteBatch,
     indexer: &mut RawIndexer,
     ranked_map: &mut RankedMap,
+    facet_map: &mut FacetMap,
+    attributes: &mut Vec<SchemaAttr>,
     key: &str,
     value: &T,
 ) -> Result<(), SerializerError>
@@ -269,10 +286,11 @@ where T: ser::Serialize,
 {
     if let Some(attr) = schema.attribute(key) {
         let props = schema.props(attr);
+        attributes.push(attr);
 
         if props.is_stored() {
             let value = rmp_serde::to_vec_named(value)?;
-            index.set_document_attribute(document_id, attr, value)?;
+            batch.set_document_attribute(document_id, attr, value);
         }
 
         if props.is_indexed() {
@@ -289,6 +307,12 @@ where T: ser::Serialize,
             let number = value.serialize(ConvertToNumber)?;
             ranked_map.insert(key, number);
         }
+
+        if props.is_faceted() {
+            for facet in value.serialize(ConvertToFacets)? {
+                facet_map.insert(document_id, attr, facet);
+            }
+        }
     }
 
     Ok(())
@@ -79,6 +79,7 @@ fn chars_count_index((n, _): (usize, usize), (i, c): (usize, char)) -> (usize, u
     (n + 1, i + c.len_utf8())
 }
 
+#[deprecated(note = "the queries are parsed by the query builder, quotes and operators included")]
 pub fn split_query_string(query: &str) -> impl Iterator<Item=&str> {
     Tokenizer::new(query).map(|t| t.word)
 }
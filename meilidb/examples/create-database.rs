@@ -12,6 +12,7 @@ use std::fs::File;
 use serde::{Serialize, Deserialize};
 use structopt::StructOpt;
 
+use meilidb_core::PostingsEncoding;
 use meilidb_data::{Database, Schema};
 
 #[derive(Debug, StructOpt)]
@@ -34,6 +35,10 @@ pub struct Opt {
 
     #[structopt(long = "update-group-size")]
     pub update_group_size: Option<usize>,
+
+    /// Store the postings of the words with the compressed encoding.
+    #[structopt(long = "compressed-postings")]
+    pub compressed_postings: bool,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -47,6 +52,7 @@ fn index(
     database_path: &Path,
     csv_data_path: &Path,
     update_group_size: Option<usize>,
+    compressed_postings: bool,
     stop_words: &HashSet<String>,
 ) -> Result<Database, Box<Error>>
 {
@@ -54,6 +60,10 @@ fn index(
 
     let index = database.create_index("default".to_string(), schema.clone())?;
 
+    if compressed_postings {
+        index.set_postings_encoding(PostingsEncoding::Compressed)?;
+    }
+
     let mut rdr = csv::Reader::from_path(csv_data_path)?;
     let mut raw_record = csv::StringRecord::new();
     let headers = rdr.headers()?.clone();
@@ -124,7 +134,14 @@ fn main() -> Result<(), Box<Error>> {
     };
 
     let start = Instant::now();
-    let result = index(schema, &opt.database_path, &opt.csv_data_path, opt.update_group_size, &stop_words);
+    let result = index(
+        schema,
+        &opt.database_path,
+        &opt.csv_data_path,
+        opt.update_group_size,
+        opt.compressed_postings,
+        &stop_words,
+    );
 
     if let Err(e) = result {
         return Err(e.into())
@@ -0,0 +1,84 @@
+use std::time::Instant;
+
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use sdset::SetBuf;
+use structopt::StructOpt;
+
+use meilidb_core::{DocIndex, DocumentId, Postings, PostingsEncoding, write_postings};
+
+#[derive(Debug, StructOpt)]
+pub struct Opt {
+    /// The number of words to generate postings for.
+    #[structopt(long = "words", default_value = "10000")]
+    pub number_words: usize,
+
+    /// The maximum number of postings of a word.
+    #[structopt(long = "max-postings", default_value = "1000")]
+    pub max_postings: usize,
+
+    /// The number of documents the postings are spread over.
+    #[structopt(long = "documents", default_value = "1000000")]
+    pub number_documents: u64,
+}
+
+fn generate_postings(rng: &mut XorShiftRng, opt: &Opt) -> SetBuf<DocIndex> {
+    let len = rng.gen_range(1, opt.max_postings + 1);
+
+    let mut indexes: Vec<_> = (0..len).map(|_| {
+        DocIndex {
+            document_id: DocumentId(rng.gen_range(0, opt.number_documents)),
+            attribute: rng.gen_range(0, 8),
+            word_index: rng.gen_range(0, 500),
+            char_index: rng.gen_range(0, 4000),
+            char_length: rng.gen_range(1, 20),
+        }
+    }).collect();
+
+    indexes.sort_unstable();
+    indexes.dedup();
+
+    SetBuf::new_unchecked(indexes)
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    let mut rng = XorShiftRng::from_seed([42; 16]);
+
+    let postings: Vec<_> = (0..opt.number_words)
+        .map(|_| generate_postings(&mut rng, &opt))
+        .collect();
+    let number_postings: usize = postings.iter().map(|p| p.len()).sum();
+    println!("{} words with {} postings", postings.len(), number_postings);
+
+    for encoding in &[PostingsEncoding::Raw, PostingsEncoding::Compressed] {
+        let start = Instant::now();
+        let encoded: Vec<_> = postings.iter().map(|indexes| {
+            let mut bytes = Vec::new();
+            write_postings(indexes, *encoding, &mut bytes);
+            bytes
+        }).collect();
+        let encode_duration = start.elapsed();
+
+        let size: usize = encoded.iter().map(Vec::len).sum();
+
+        let start = Instant::now();
+        let mut decoded = 0;
+        for bytes in encoded {
+            let postings = Postings::from_bytes(bytes).unwrap();
+            decoded += postings.iter().count();
+        }
+        let decode_duration = start.elapsed();
+
+        assert_eq!(decoded, number_postings);
+
+        println!(
+            "{:?}: {} bytes ({:.2} bytes by posting), encoded in {:.2?}, decoded in {:.2?}",
+            encoding,
+            size,
+            size as f64 / number_postings as f64,
+            encode_duration,
+            decode_duration,
+        );
+    }
+}
@@ -14,7 +14,7 @@ use structopt::StructOpt;
 use meilidb_core::Match;
 
 use meilidb_data::schema::SchemaAttr;
-use meilidb_data::Database;
+use meilidb_data::{Database, QueryBuilderExt};
 
 #[derive(Debug, StructOpt)]
 pub struct Opt {
@@ -32,6 +32,10 @@ pub struct Opt {
     /// The number of characters before and after the first match
     #[structopt(short = "C", long = "context", default_value = "35")]
     pub char_context: usize,
+
+    /// A filter expression the returned documents must match
+    #[structopt(short = "f", long = "filter")]
+    pub filter: Option<String>,
 }
 
 type Document = HashMap<String, String>;
@@ -126,7 +130,7 @@ fn crop_text(
             (m.char_index as usize) + (m.char_length as usize) <= start + (context * 2)
         })
         .map(|match_| {
-            Match { char_index: match_.char_index - start as u16, ..match_ }
+            Match { char_index: match_.char_index - start as u32, ..match_ }
         })
         .collect();
 
@@ -161,12 +165,15 @@ fn main() -> Result<(), Box<Error>> {
         let start_total = Instant::now();
 
         let builder = index.query_builder();
-        let documents = builder.query(query, 0..opt.number_results);
+        let result = match &opt.filter {
+            Some(filter) => builder.with_filter_expr(filter)?.query(query, 0..opt.number_results)?,
+            None => builder.query(query, 0..opt.number_results)?,
+        };
 
         let mut retrieve_duration = Duration::default();
 
-        let number_of_documents = documents.len();
-        for mut doc in documents {
+        let number_of_documents = result.hits.len();
+        for mut doc in result.hits {
 
             doc.matches.sort_unstable_by_key(|m| (m.char_index, m.char_index));
 
@@ -207,6 +214,9 @@ fn main() -> Result<(), Box<Error>> {
         }
 
         eprintln!("document field retrieve took {:.2?}", retrieve_duration);
+        let estimated = if result.exhaustive { "" } else { " (estimated)" };
+        eprintln!("query took {:.2?}", result.processing_time);
+        eprintln!("{} total hits{}", result.total_hits, estimated);
         eprintln!("===== Found {} results in {:.2?} =====", number_of_documents, start_total.elapsed());
         buffer.clear();
     }
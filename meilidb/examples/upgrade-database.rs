@@ -0,0 +1,55 @@
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct Opt {
+    /// The database written by a MeiliDB version using sled 0.23.
+    #[structopt(parse(from_os_str))]
+    pub old_database_path: PathBuf,
+
+    /// The path where the upgraded database will be written, it must not exist.
+    #[structopt(parse(from_os_str))]
+    pub new_database_path: PathBuf,
+}
+
+/// Copies every tree of the old database into a database in the current
+/// sled format, the content of the indexes is migrated when they are opened.
+fn upgrade(opt: &Opt) -> Result<(), Box<Error>> {
+    if opt.new_database_path.exists() {
+        return Err(format!("{:?} already exists", opt.new_database_path).into())
+    }
+
+    let old = sled023::Db::start_default(&opt.old_database_path)?;
+    let new = sled::Db::open(&opt.new_database_path)?;
+
+    for name in old.tree_names() {
+        let old_tree = old.open_tree(name.clone())?;
+        let new_tree = new.open_tree(name.clone())?;
+
+        let mut count = 0;
+        for result in old_tree.iter() {
+            let (key, value) = result?;
+            new_tree.insert(key, value.to_vec())?;
+            count += 1;
+        }
+
+        println!("{}: {} entries copied", String::from_utf8_lossy(&name), count);
+    }
+
+    new.flush()?;
+
+    Ok(())
+}
+
+fn main() -> Result<(), Box<Error>> {
+    let opt = Opt::from_args();
+
+    let start = Instant::now();
+    upgrade(&opt)?;
+    println!("database upgraded in {:.2?}", start.elapsed());
+
+    Ok(())
+}